@@ -49,7 +49,28 @@ pub enum TokenError {
     StreamedAmt,
     // cannot cancel this transaction
     #[error("cannot cancel this transaction")]
-    CancelNotAllowed
+    CancelNotAllowed,
+    // Withdraw before the vesting cliff
+    #[error("Stream cliff has not been reached")]
+    CliffNotReached,
+    // Crank called again before the stream's crank interval
+    #[error("Stream was cranked too recently")]
+    CrankTooSoon,
+    // Pause policy of the stream does not allow this pause or resume
+    #[error("Stream cannot be paused")]
+    PauseNotAllowed,
+    // Metadata edited after the stream started
+    #[error("Stream metadata can no longer be changed")]
+    MetadataLocked,
+    // Closing a stream that still has funds to stream
+    #[error("Stream has not finished yet")]
+    StreamNotFinished,
+    // Stream is frozen by its arbiter
+    #[error("Stream is frozen by its arbiter")]
+    StreamFrozen,
+    // Legacy stream account has no room for the new stream fields
+    #[error("Stream account uses the legacy layout")]
+    LegacyLayout,
 }
 impl From<TokenError> for ProgramError {
     fn from(e: TokenError) -> Self {
@@ -1,12 +1,13 @@
 //! Instruction types
 use solana_program::{
     program_error::ProgramError,
+    pubkey::Pubkey,
 };
-use {borsh::{BorshDeserialize}};
+use {borsh::{BorshDeserialize, BorshSerialize}};
 
 use crate::{
     error::TokenError,
-    state::{Multisig,EscrowMultisig,TokenEscrowMultisig,SolTransfer,TokenTransfer}
+    state::{Multisig,EscrowMultisig,TokenEscrowMultisig,SolTransfer,TokenTransfer,StreamMetadata,Breakpoint,Beneficiary}
 };
 use std::convert::TryInto;
 
@@ -67,6 +68,57 @@ pub struct ProcessSolTransferStream{
     /// Amount of fund
     pub amount: u64,
 }
+/// Single stream of a batch create
+#[derive(BorshDeserialize, Debug, Clone)]
+pub struct BatchStream{
+    pub recipient: Pubkey,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub amount: u64,
+    /// Derives the stream address from the sender, recipient and this nonce, keypair account when unset
+    pub nonce: Option<u64>,
+}
+/// Layout version of `StreamTerms`, sent right after the instruction tag
+pub const STREAM_TERMS_VERSION: u8 = 1;
+/// Terms a client sets when creating a stream with a custom vesting schedule,
+/// everything else on the stream is set by the program
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct StreamTerms{
+    pub start_time: u64,
+    pub end_time: u64,
+    pub amount: u64,
+    /// Nothing is withdrawable before this time, 0 when the stream has no cliff
+    pub cliff_time: u64,
+    pub cliff_amount: u64,
+    /// Funds unlock in steps of this many seconds, 0 for per second streaming
+    pub release_interval: u64,
+    pub breakpoints: Vec<Breakpoint>,
+    /// Mints a 1-of-1 token representing the recipient position, at an address derived from the stream
+    pub tokenized: bool,
+    pub crank_tip: u64,
+    pub crank_interval: u64,
+    pub renewable: bool,
+    /// Units streamed per second by an open-ended stream, 0 for a fixed amount and end_time
+    pub rate: u64,
+    pub cancel_policy: u8,
+    pub pause_policy: u8,
+    pub pause_forfeits: bool,
+    pub metadata: StreamMetadata,
+    /// Derives the stream address from the sender, recipient and this nonce, keypair account when unset
+    pub nonce: Option<u64>,
+    pub beneficiaries: Vec<Beneficiary>,
+    pub recipient_must_sign: bool,
+    pub arbiter: Pubkey,
+}
+impl StreamTerms {
+    fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&version, rest) = input.split_first().ok_or(TokenError::InvalidInstruction)?;
+        if version != STREAM_TERMS_VERSION {
+            return Err(TokenError::InvalidInstruction.into());
+        }
+        Ok(Self::try_from_slice(rest)?)
+    }
+}
 pub struct ProcessSet {
     pub number : u64,
 }
@@ -93,13 +145,15 @@ pub enum TokenInstruction {
     ProcessSwapSol(ProcessSwapSol),
     ProcessSwapToken(ProcessSwapToken),
     SignedBy,
-    ProcessSolMultiSigStream{whitelist_v3:EscrowMultisig},
+    /// `tokenized` mints a 1-of-1 token representing the recipient position
+    ProcessSolMultiSigStream{whitelist_v3:EscrowMultisig,tokenized:bool,pause_policy:u8,pause_forfeits:bool,metadata:StreamMetadata},
     ProcessSolWithdrawStreamMultisig(ProcessSolWithdrawStreamMultisig),
     ProcessSolCancelStreamMultisig,
     ProcessPauseMultisigStream,
     ProcessResumeMultisigStream,
     ProcessRejectMultisigStream,
-    ProcessSolTokenMultiSigStream{whitelist_v4:TokenEscrowMultisig},
+    /// `tokenized` mints a 1-of-1 token representing the recipient position
+    ProcessSolTokenMultiSigStream{whitelist_v4:TokenEscrowMultisig,tokenized:bool,pause_policy:u8,pause_forfeits:bool,metadata:StreamMetadata},
     ProcessTokenWithdrawStreamMultisig(ProcessTokenWithdrawStreamMultisig),
     ProcessTokenCancelStreamMultisig,
     ProcessPauseTokenMultisigStream,
@@ -112,6 +166,76 @@ pub enum TokenInstruction {
     SignedByTransferToken,
     ProcessRejectTransferSol,
     ProcessRejectTransferToken,
+    /// Initialize a solana stream with a custom vesting schedule
+    ProcessSolVestingStream{data:StreamTerms},
+    /// Initialize a token stream with a custom vesting schedule
+    ProcessTokenVestingStream{data:StreamTerms},
+    /// Hand over a solana stream to a new recipient, signed by the current recipient
+    ProcessUpdateSolRecipient,
+    /// Hand over a token stream to a new recipient, signed by the current recipient
+    ProcessUpdateTokenRecipient,
+    /// Move a solana stream and its locked funds to a new sender, signed by the current and new sender
+    ProcessUpdateSolSender,
+    /// Move a token stream and its locked funds to a new sender, signed by the current and new sender
+    ProcessUpdateTokenSender,
+    /// Push the vested funds of a solana stream to its recipient, callable by any keeper
+    ProcessCrankSolStream,
+    /// Push the vested funds of a token stream to its recipient, callable by any keeper
+    ProcessCrankTokenStream,
+    /// Initialize several solana streams from one sender
+    ProcessSolBatchStream{streams:Vec<BatchStream>},
+    /// Initialize several token streams of one mint from one sender
+    ProcessTokenBatchStream{streams:Vec<BatchStream>},
+    /// Withdraw everything vested from several solana streams of one recipient
+    ProcessSolBatchWithdraw,
+    /// Withdraw everything vested from several token streams of one recipient and mint
+    ProcessTokenBatchWithdraw,
+    /// Add funds to a running solana stream, `extend_end` keeps the rate instead of end_time
+    ProcessTopUpSolStream{amount:u64,extend_end:bool},
+    /// Add funds to a running token stream, `extend_end` keeps the rate instead of end_time
+    ProcessTopUpTokenStream{amount:u64,extend_end:bool},
+    /// Pause a solana stream from pause_start to pause_end, resuming on its own
+    ProcessScheduleSolPause{pause_start:u64,pause_end:u64},
+    /// Pause a token stream from pause_start to pause_end, resuming on its own
+    ProcessScheduleTokenPause{pause_start:u64,pause_end:u64},
+    /// Lower the amount of a solana stream, releasing the difference to the sender
+    ProcessReduceSolStream{amount:u64},
+    /// Lower the amount of a token stream, releasing the difference to the sender
+    ProcessReduceTokenStream{amount:u64},
+    /// Replace the metadata of a solana stream that has not started yet
+    ProcessUpdateSolMetadata{metadata:StreamMetadata},
+    /// Replace the metadata of a token stream that has not started yet
+    ProcessUpdateTokenMetadata{metadata:StreamMetadata},
+    /// Replace the metadata of a solana multisig stream that has not started yet
+    ProcessUpdateSolMultisigMetadata{metadata:StreamMetadata},
+    /// Replace the metadata of a token multisig stream that has not started yet
+    ProcessUpdateTokenMultisigMetadata{metadata:StreamMetadata},
+    /// Close a finished solana stream and return its rent, optionally with the sender's unused withdraw data
+    ProcessCloseSolStream,
+    /// Close a finished token stream and return its rent, optionally with the sender's unused withdraw data and empty vault
+    ProcessCloseTokenStream,
+    /// Close a finished solana multisig stream and return its rent
+    ProcessCloseSolMultisigStream,
+    /// Close a finished token multisig stream and return its rent
+    ProcessCloseTokenMultisigStream,
+    /// Withdraw the share of one beneficiary of a split solana stream, anyone can pay it out unless the
+    /// stream requires the recipient's signature
+    ProcessSolBeneficiaryWithdraw,
+    /// Withdraw the share of one beneficiary of a split token stream, signed by the beneficiary as it pays
+    /// for any token account created
+    ProcessTokenBeneficiaryWithdraw,
+    /// Recipient signed solana withdraw paying the last account instead of the recipient
+    ProcessSolWithdrawStreamTo{amount:u64},
+    /// Recipient signed token withdraw paying the last account, a token account of the stream mint
+    ProcessTokenWithdrawStreamTo{amount:u64},
+    /// Arbiter freezes a disputed solana stream
+    ProcessFreezeSolStream,
+    /// Arbiter freezes a disputed token stream
+    ProcessFreezeTokenStream,
+    /// Arbiter settles a frozen solana stream in favour of the recipient or the sender
+    ProcessResolveSolStream{release_to_recipient:bool},
+    /// Arbiter settles a frozen token stream in favour of the recipient or the sender
+    ProcessResolveTokenStream{release_to_recipient:bool},
 }
 impl TokenInstruction {
     /// Unpacks a byte buffer into a [TokenInstruction](enum.TokenInstruction.html).
@@ -220,7 +344,17 @@ impl TokenInstruction {
                 Self::SignedBy
             }
             20 => {
-                Self::ProcessSolMultiSigStream{whitelist_v3:EscrowMultisig::try_from_slice(rest)?}
+                let mut rest = rest;
+                let whitelist_v3 = EscrowMultisig::deserialize(&mut rest)?;
+                // Optional trailing flags, absent on untokenized streams
+                let tokenized = rest.first() == Some(&1);
+                let pause_policy = rest.get(1).copied().unwrap_or(0);
+                let pause_forfeits = rest.get(2) == Some(&1);
+                let metadata = match rest.get(3..) {
+                    Some(metadata) if !metadata.is_empty() => StreamMetadata::try_from_slice(metadata)?,
+                    _ => StreamMetadata::default(),
+                };
+                Self::ProcessSolMultiSigStream{whitelist_v3,tokenized,pause_policy,pause_forfeits,metadata}
             }
             21 => {
                 let (amount, _rest) = rest.split_at(8);
@@ -240,7 +374,17 @@ impl TokenInstruction {
                 Self:: ProcessRejectMultisigStream
             }
             26 => {
-                Self::ProcessSolTokenMultiSigStream{whitelist_v4:TokenEscrowMultisig::try_from_slice(rest)?}
+                let mut rest = rest;
+                let whitelist_v4 = TokenEscrowMultisig::deserialize(&mut rest)?;
+                // Optional trailing flags, absent on untokenized streams
+                let tokenized = rest.first() == Some(&1);
+                let pause_policy = rest.get(1).copied().unwrap_or(0);
+                let pause_forfeits = rest.get(2) == Some(&1);
+                let metadata = match rest.get(3..) {
+                    Some(metadata) if !metadata.is_empty() => StreamMetadata::try_from_slice(metadata)?,
+                    _ => StreamMetadata::default(),
+                };
+                Self::ProcessSolTokenMultiSigStream{whitelist_v4,tokenized,pause_policy,pause_forfeits,metadata}
             }
             27 => {
                 let (amount, _rest) = rest.split_at(8);
@@ -280,7 +424,175 @@ impl TokenInstruction {
             38 => {
                 Self::ProcessRejectTransferToken
             }
+            39 => {
+                Self::ProcessSolVestingStream{data:StreamTerms::unpack(rest)?}
+            }
+            40 => {
+                Self::ProcessTokenVestingStream{data:StreamTerms::unpack(rest)?}
+            }
+            41 => {
+                Self::ProcessUpdateSolRecipient
+            }
+            42 => {
+                Self::ProcessUpdateTokenRecipient
+            }
+            43 => {
+                Self::ProcessUpdateSolSender
+            }
+            44 => {
+                Self::ProcessUpdateTokenSender
+            }
+            45 => {
+                Self::ProcessCrankSolStream
+            }
+            46 => {
+                Self::ProcessCrankTokenStream
+            }
+            47 => {
+                Self::ProcessSolBatchStream{streams:Vec::<BatchStream>::try_from_slice(rest)?}
+            }
+            48 => {
+                Self::ProcessTokenBatchStream{streams:Vec::<BatchStream>::try_from_slice(rest)?}
+            }
+            49 => {
+                Self::ProcessSolBatchWithdraw
+            }
+            50 => {
+                Self::ProcessTokenBatchWithdraw
+            }
+            51 => {
+                let (amount, rest) = unpack_u64(rest)?;
+                let extend_end = rest.first() == Some(&1);
+                Self::ProcessTopUpSolStream{amount,extend_end}
+            }
+            52 => {
+                let (amount, rest) = unpack_u64(rest)?;
+                let extend_end = rest.first() == Some(&1);
+                Self::ProcessTopUpTokenStream{amount,extend_end}
+            }
+            53 => {
+                let (pause_start, rest) = unpack_u64(rest)?;
+                let (pause_end, _rest) = unpack_u64(rest)?;
+                Self::ProcessScheduleSolPause{pause_start,pause_end}
+            }
+            54 => {
+                let (pause_start, rest) = unpack_u64(rest)?;
+                let (pause_end, _rest) = unpack_u64(rest)?;
+                Self::ProcessScheduleTokenPause{pause_start,pause_end}
+            }
+            55 => {
+                let (amount, _rest) = unpack_u64(rest)?;
+                Self::ProcessReduceSolStream{amount}
+            }
+            56 => {
+                let (amount, _rest) = unpack_u64(rest)?;
+                Self::ProcessReduceTokenStream{amount}
+            }
+            57 => {
+                Self::ProcessUpdateSolMetadata{metadata:StreamMetadata::try_from_slice(rest)?}
+            }
+            58 => {
+                Self::ProcessUpdateTokenMetadata{metadata:StreamMetadata::try_from_slice(rest)?}
+            }
+            59 => {
+                Self::ProcessUpdateSolMultisigMetadata{metadata:StreamMetadata::try_from_slice(rest)?}
+            }
+            60 => {
+                Self::ProcessUpdateTokenMultisigMetadata{metadata:StreamMetadata::try_from_slice(rest)?}
+            }
+            61 => {
+                Self::ProcessCloseSolStream
+            }
+            62 => {
+                Self::ProcessCloseTokenStream
+            }
+            63 => {
+                Self::ProcessCloseSolMultisigStream
+            }
+            64 => {
+                Self::ProcessCloseTokenMultisigStream
+            }
+            65 => {
+                Self::ProcessSolBeneficiaryWithdraw
+            }
+            66 => {
+                Self::ProcessTokenBeneficiaryWithdraw
+            }
+            67 => {
+                let (amount, _rest) = unpack_u64(rest)?;
+                Self::ProcessSolWithdrawStreamTo{amount}
+            }
+            68 => {
+                let (amount, _rest) = unpack_u64(rest)?;
+                Self::ProcessTokenWithdrawStreamTo{amount}
+            }
+            69 => {
+                Self::ProcessFreezeSolStream
+            }
+            70 => {
+                Self::ProcessFreezeTokenStream
+            }
+            71 => {
+                let release_to_recipient = rest.first() == Some(&1);
+                Self::ProcessResolveSolStream{release_to_recipient}
+            }
+            72 => {
+                let release_to_recipient = rest.first() == Some(&1);
+                Self::ProcessResolveTokenStream{release_to_recipient}
+            }
             _ => return Err(TokenError::InvalidInstruction.into()),
         })
     }
 }
+/// Reads a little endian u64 off the front of `input`, rejecting truncated data
+fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+    let value = input
+        .get(..8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(TokenError::InvalidInstruction)?;
+    Ok((value, &input[8..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_amounts_are_rejected() {
+        for tag in [51u8, 52, 53, 54, 55, 56, 67, 68] {
+            assert_eq!(TokenInstruction::unpack(&[tag, 1, 2, 3]).err(), Some(TokenError::InvalidInstruction.into()));
+        }
+        // Pause windows need both ends
+        for tag in [53u8, 54] {
+            let mut data = vec![tag];
+            data.extend_from_slice(&7u64.to_le_bytes());
+            data.extend_from_slice(&[1, 2, 3]);
+            assert_eq!(TokenInstruction::unpack(&data).err(), Some(TokenError::InvalidInstruction.into()));
+        }
+        let mut data = vec![51u8];
+        data.extend_from_slice(&7u64.to_le_bytes());
+        data.push(1);
+        assert!(matches!(
+            TokenInstruction::unpack(&data),
+            Ok(TokenInstruction::ProcessTopUpSolStream{amount: 7, extend_end: true})
+        ));
+    }
+
+    #[test]
+    fn vesting_stream_terms_are_versioned() {
+        let terms = StreamTerms { start_time: 10, end_time: 20, amount: 7, nonce: Some(3), ..Default::default() };
+        let mut data = vec![39u8, STREAM_TERMS_VERSION];
+        data.extend_from_slice(&terms.try_to_vec().unwrap());
+        match TokenInstruction::unpack(&data) {
+            Ok(TokenInstruction::ProcessSolVestingStream{data}) => {
+                assert_eq!((data.start_time, data.end_time, data.amount, data.nonce), (10, 20, 7, Some(3)));
+            }
+            _ => panic!("expected a solana vesting stream"),
+        }
+        // Unknown layouts and a missing version are rejected
+        data[1] = STREAM_TERMS_VERSION + 1;
+        assert_eq!(TokenInstruction::unpack(&data).err(), Some(TokenError::InvalidInstruction.into()));
+        assert_eq!(TokenInstruction::unpack(&[40u8]).err(), Some(TokenError::InvalidInstruction.into()));
+    }
+}
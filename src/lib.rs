@@ -16,11 +16,44 @@ pub const PREFIX: &str = "withdraw_sol";
 pub const PREFIX_TOKEN: &str = "withdraw_token";
 pub const PREFIXMULTISIG: &str = "withdraw_multisig_sol";
 pub const PREFIXMULTISIGSAFE: &str = "multisig_safe";
+pub const PREFIX_RECEIVABLE: &str = "stream_receivable";
+pub const PREFIX_STREAM: &str = "stream";
 
 /// Minimum number of multi-signature signers (min N)
 pub const MIN_SIGNERS: usize = 1;
 /// Maximum number of multi-signature signers (max N)
 pub const MAX_SIGNERS: usize = 11;
+/// Maximum number of breakpoints on a custom vesting curve
+pub const MAX_BREAKPOINTS: usize = 32;
+/// Maximum number of beneficiaries splitting a stream
+pub const MAX_BENEFICIARIES: usize = 10;
+/// Maximum number of streams created by a single batch instruction
+pub const MAX_BATCH_STREAMS: usize = 10;
+/// Only the sender can cancel the stream
+pub const CANCEL_BY_SENDER: u8 = 0;
+/// Only the recipient can cancel the stream
+pub const CANCEL_BY_RECIPIENT: u8 = 1;
+/// Either the sender or the recipient can cancel the stream
+pub const CANCEL_BY_BOTH: u8 = 2;
+/// Nobody can cancel the stream
+pub const CANCEL_BY_NOBODY: u8 = 3;
+/// Either the sender or the recipient can pause the stream
+pub const PAUSE_BY_BOTH: u8 = 0;
+/// Only the sender can pause the stream
+pub const PAUSE_BY_SENDER: u8 = 1;
+/// The stream cannot be paused
+pub const PAUSE_NONE: u8 = 2;
+/// Marks stream accounts written with a version header, older accounts keep their fixed layout
+pub const STREAM_MAGIC: [u8; 6] = *b"zbcstr";
+/// Current version of the stream account layout
+pub const STREAM_VERSION: u8 = 1;
+/// Magic, version and kind bytes in front of the stream data
+pub const STREAM_HEADER_LEN: usize = 8;
+/// Kinds of stream account, stored in the last header byte
+pub const KIND_SOL_STREAM: u8 = 1;
+pub const KIND_TOKEN_STREAM: u8 = 2;
+pub const KIND_SOL_MULTISIG_STREAM: u8 = 3;
+pub const KIND_TOKEN_MULTISIG_STREAM: u8 = 4;
 
 entrypoint!(process_instruction);
 fn process_instruction(
@@ -29,8 +29,10 @@ use crate::{
         ProcessSwapToken,
         ProcessSolWithdrawStreamMultisig,
         ProcessTokenWithdrawStreamMultisig,
+        BatchStream,
+        StreamTerms,
     },
-    state::{Stream,StreamToken,StreamMultisig,TokenStreamMultisig,Escrow,TokenEscrow,Withdraw,TokenWithdraw,Multisig,WhiteList,TokenEscrowMultisig,EscrowMultisig,SolTransfer,TokenTransfer},
+    state::{StreamSchedule,StreamAccount,StreamMetadata,StreamSeeds,Stream,StreamToken,StreamMultisig,TokenStreamMultisig,Escrow,TokenEscrow,Withdraw,TokenWithdraw,Multisig,WhiteList,TokenEscrowMultisig,EscrowMultisig,SolTransfer,TokenTransfer},
     error::{TokenError},
 
     utils::{
@@ -42,12 +44,28 @@ use crate::{
         create_pda_account_signed,
         get_multisig_data_and_bump_seed,
         get_token_withdraw_data_and_bump_seed,
-        get_token_balance
+        get_token_balance,
+        get_receivable_mint_and_bump_seed,
+        create_receivable_mint,
+        assert_token_account_mint,
+        assert_cancel_allowed,
+        assert_pause_allowed,
+        assert_valid_pause_terms,
+        assert_valid_metadata,
+        create_stream_account,
+        assert_stream_address,
+        close_program_account,
+        assert_stream_payee,
+        rent_refund_address
     },
     PREFIX,
     PREFIXMULTISIG,
     PREFIX_TOKEN,
+    PREFIX_RECEIVABLE,
     PREFIXMULTISIGSAFE,
+    CANCEL_BY_SENDER,
+    CANCEL_BY_BOTH,
+    MAX_BATCH_STREAMS,
 };
 use spl_associated_token_account::get_associated_token_address;
 use std::str::FromStr;
@@ -57,6 +75,16 @@ pub struct Processor {}
 impl Processor {
     /// Function to initialize a solana
     pub fn process_sol_stream(program_id: &Pubkey, accounts: &[AccountInfo], start_time: u64, end_time: u64, amount: u64) -> ProgramResult {
+        let data = StreamTerms {
+            start_time,
+            end_time,
+            amount,
+            ..Default::default()
+        };
+        Self::process_sol_vesting_stream(program_id, accounts, data)
+    }
+    /// Function to initialize a solana stream with a custom vesting schedule
+    pub fn process_sol_vesting_stream(program_id: &Pubkey, accounts: &[AccountInfo], data: StreamTerms) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let source_account_info = next_account_info(account_info_iter)?;  //sender
         let dest_account_info = next_account_info(account_info_iter)?; // recipient
@@ -68,14 +96,55 @@ impl Processor {
         if !source_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
         }
+        // Stream lives at an address clients can recompute from its parties
+        let seeds = match data.nonce {
+            Some(nonce) => StreamSeeds {
+                sender: *source_account_info.key,
+                recipient: *dest_account_info.key,
+                nonce,
+                bump: 0,
+            },
+            None => StreamSeeds::default(),
+        };
+        let mut escrow = Stream {
+            start_time: data.start_time,
+            end_time: data.end_time,
+            amount: data.amount,
+            sender: *source_account_info.key,
+            recipient: *dest_account_info.key,
+            cliff_time: data.cliff_time,
+            cliff_amount: data.cliff_amount,
+            release_interval: data.release_interval,
+            breakpoints: data.breakpoints,
+            crank_tip: data.crank_tip,
+            crank_interval: data.crank_interval,
+            renewable: data.renewable,
+            periods: 1,
+            rate: data.rate,
+            cancel_policy: data.cancel_policy,
+            pause_policy: data.pause_policy,
+            pause_forfeits: data.pause_forfeits,
+            metadata: data.metadata,
+            seeds,
+            rent_payer: *source_account_info.key,
+            beneficiaries: data.beneficiaries,
+            recipient_must_sign: data.recipient_must_sign,
+            arbiter: data.arbiter,
+            ..Default::default()
+        };
+        // Receivable mint is derived from the stream address and created with the stream
+        if data.tokenized {
+            let (mint_address, _bump_seed) = get_receivable_mint_and_bump_seed(
+                PREFIX_RECEIVABLE,
+                pda_data.key,
+                program_id,
+            );
+            escrow.receivable_mint = mint_address;
+        }
         // current time in unix time
         let now = Clock::get()?.unix_timestamp as u64; 
-        if now >= end_time{
-            return Err(TokenError::TimeEnd.into());
-        }
-        if start_time >= end_time {
-            return Err(TokenError::InvalidInstruction.into());
-        }
+        escrow.assert_valid_terms(source_account_info.key, dest_account_info.key, now)?;
+        escrow.end_time = escrow.effective_end_time();
         assert_keys_equal(system_program::id(), *system_program.key)?;
         if !pda_data.data_is_empty(){
             return Err(TokenError::StreamAlreadyCreated.into());
@@ -104,32 +173,107 @@ impl Processor {
             )?;
         }
         let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
-        withdraw_state.amount += amount;
+        withdraw_state.amount += escrow.amount;
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
 
-        let transfer_amount =  rent.minimum_balance(std::mem::size_of::<Stream>());
-        // Sending transaction fee to recipient. So, he can withdraw the streamed fund
-        create_pda_account( 
+        // Account is sized to the serialized stream so it grows with the breakpoints
+        let space_size = escrow.account_len()?;
+        escrow.seeds = create_stream_account(
             source_account_info,
-            transfer_amount,
-            std::mem::size_of::<Stream>(),
+            &Pubkey::default(),
+            escrow.seeds,
+            space_size,
             program_id,
             system_program,
             pda_data
         )?;
-        let mut escrow = Stream::try_from_slice(&pda_data.data.borrow())?;
-        escrow.start_time = start_time;
-        escrow.end_time = end_time;
-        escrow.paused = 0;
-        escrow.withdraw_limit = 0;
-        escrow.sender = *source_account_info.key;
-        escrow.recipient = *dest_account_info.key;
-        escrow.amount = amount;
-        escrow.withdrawn = 0 ;
-        escrow.paused_at = 0;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        if data.tokenized {
+            create_receivable_mint(program_id, pda_data.key, source_account_info, dest_account_info, system_program, account_info_iter)?;
+        }
+        escrow.init(pda_data)?;
         Ok(())
     }
+    // Open-ended streams reserve nothing, so they can only be paid from the sender's free balance
+    fn rate_stream_cap(amount: u64, vault_balance: u64, reserved: u64) -> u64 {
+        let free_amount = vault_balance.saturating_sub(reserved);
+        if amount > free_amount {
+            msg!("Stream debt: {}", amount - free_amount);
+            return free_amount;
+        }
+        amount
+    }
+    // Account the rent of a finished stream goes back to: the sender when it paid the rent, otherwise
+    // the rent payer following the other accounts. None when the rent payer was left out.
+    fn rent_payer_account<'a, 'b>(
+        rent_payer: &Pubkey,
+        sender: &Pubkey,
+        source_account_info: &'b AccountInfo<'a>,
+        account_info_iter: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+    ) -> Result<Option<&'b AccountInfo<'a>>, ProgramError> {
+        let rent_payer = rent_refund_address(rent_payer, sender);
+        if rent_payer == *source_account_info.key {
+            return Ok(Some(source_account_info));
+        }
+        match next_account_info(account_info_iter) {
+            Ok(rent_payer_info) => {
+                assert_keys_equal(rent_payer, *rent_payer_info.key)?;
+                Ok(Some(rent_payer_info))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+    // Rolls a renewable solana stream over, reserving the new periods in the sender's withdraw data. Returns the amount reserved
+    fn renew_sol_stream(program_id: &Pubkey, escrow: &mut Stream, pda: &AccountInfo, withdraw_data: &AccountInfo, now: u64) -> Result<u64, ProgramError> {
+        if !escrow.renewable {
+            return Ok(0);
+        }
+        let (account_address, _bump_seed) = get_master_address_and_bump_seed(
+            &escrow.sender,
+            program_id,
+        );
+        assert_keys_equal(*pda.key,account_address )?;
+        let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+            PREFIX,
+            &escrow.sender,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        let free_amount = pda.lamports().saturating_sub(withdraw_state.amount);
+        let reserved = escrow.renew(now, free_amount)?;
+        if reserved > 0 {
+            withdraw_state.amount = withdraw_state.amount.checked_add(reserved).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        }
+        Ok(reserved)
+    }
+    // Rolls a renewable token stream over, reserving the new periods in the sender's withdraw data. Returns the amount reserved
+    fn renew_token_stream(program_id: &Pubkey, escrow: &mut StreamToken, pda_associated_info: &AccountInfo, withdraw_data: &AccountInfo, now: u64) -> Result<u64, ProgramError> {
+        if !escrow.renewable {
+            return Ok(0);
+        }
+        let (account_address, _bump_seed) = get_master_address_and_bump_seed(
+            &escrow.sender,
+            program_id,
+        );
+        let pda_associated_token = get_associated_token_address(&account_address,&escrow.token_mint);
+        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            &escrow.sender,
+            &escrow.token_mint,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        let free_amount = get_token_balance(pda_associated_info)?.saturating_sub(withdraw_state.amount);
+        let reserved = escrow.renew(now, free_amount)?;
+        if reserved > 0 {
+            withdraw_state.amount = withdraw_state.amount.checked_add(reserved).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        }
+        Ok(reserved)
+    }
     // This function will be removed in future
     pub fn process_sol_withdraw_stream_deprecated(program_id: &Pubkey,accounts: &[AccountInfo],amount: u64) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
@@ -164,10 +308,7 @@ impl Processor {
             return Err(TokenError::StreamNotStarted.into());
         }
         // Recipient can only withdraw the money that is already streamed. 
-        let mut allowed_amt = escrow.allowed_amt(now);
-        if now >= escrow.end_time {
-            allowed_amt = escrow.amount;
-        }
+        let allowed_amt = escrow.withdrawable_amount(now)?;
         msg!("You can withdraw {}",allowed_amt);
         if !dest_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
@@ -179,10 +320,6 @@ impl Processor {
         if amount>allowed_amt {
             return Err(ProgramError::InsufficientFunds);
         }
-        // Checking if paused stream is greater than withdraw limit
-        if escrow.paused == 1 && amount > escrow.withdraw_limit {
-            return Err(ProgramError::InsufficientFunds);
-        }
         let (_account_address, bump_seed) = get_master_address_and_bump_seed(
             source_account_info.key,
             program_id,
@@ -229,6 +366,14 @@ impl Processor {
     }
     /// Function to withdraw solana
     fn process_sol_withdraw_stream(program_id: &Pubkey,accounts: &[AccountInfo],amount: u64) -> ProgramResult {
+        Self::sol_withdraw_stream(program_id, accounts, amount, None)
+    }
+    /// Function for the recipient to withdraw a solana stream to a wallet of its choice
+    fn process_sol_withdraw_stream_to(program_id: &Pubkey,accounts: &[AccountInfo],amount: u64) -> ProgramResult {
+        let (destination_info, accounts) = accounts.split_last().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        Self::sol_withdraw_stream(program_id, accounts, amount, Some(destination_info))
+    }
+    fn sol_withdraw_stream<'a>(program_id: &Pubkey,accounts: &[AccountInfo<'a>],amount: u64,destination: Option<&AccountInfo<'a>>) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let source_account_info = next_account_info(account_info_iter)?; // stream initiator address
         let dest_account_info = next_account_info(account_info_iter)?; // stream receiver
@@ -241,7 +386,7 @@ impl Processor {
         if fee_account.key != fee_receiver {
             return Err(TokenError::OwnerMismatch.into());
         }
-        if *pda_data.owner != *program_id && *withdraw_data.owner != *program_id{
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
             return Err(ProgramError::InvalidArgument);
         }
         let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
@@ -253,34 +398,60 @@ impl Processor {
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
-        let mut escrow = Stream::try_from_slice(&pda_data.data.borrow())?;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        // Payout comes out of the vault of the stream sender
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let (account_address, _bump_seed) = get_master_address_and_bump_seed(
+            &escrow.sender,
+            program_id,
+        );
+        assert_keys_equal(*pda.key, account_address)?;
         let now = Clock::get()?.unix_timestamp as u64;
+        Self::renew_sol_stream(program_id, &mut escrow, pda, withdraw_data, now)?;
         if now <= escrow.start_time {
             return Err(TokenError::StreamNotStarted.into());
         }
+        if now < escrow.cliff_time {
+            return Err(TokenError::CliffNotReached.into());
+        }
         // Recipient can only withdraw the money that is already streamed. 
-        let mut allowed_amt = escrow.allowed_amt(now);
-        msg!("{}",allowed_amt);
-        if now >= escrow.end_time {
-            allowed_amt = escrow.amount;
+        let mut allowed_amt = escrow.withdrawable_amount(now)?;
+        if escrow.rate != 0 {
+            let withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            allowed_amt = Self::rate_stream_cap(allowed_amt, pda.lamports(), withdraw_state.amount);
         }
-        allowed_amt -=  escrow.withdrawn;
         msg!("You can withdraw {}",allowed_amt);
-        // if !dest_account_info.is_signer {
-        //     return Err(ProgramError::MissingRequiredSignature); 
-        // }
-        msg!("{:?}",escrow);
-        if *dest_account_info.key != escrow.recipient {
+        // Recipient can require its signature to decide when it gets paid
+        if escrow.recipient_must_sign && !dest_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        // Split streams are paid out to each beneficiary separately
+        if !escrow.beneficiaries.is_empty() {
+            msg!("Split streams are withdrawn by their beneficiaries");
             return Err(TokenError::EscrowMismatch.into());
         }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        // Only the recipient can send its payout somewhere else
+        let receiver_info = match destination {
+            Some(destination) => {
+                if !dest_account_info.is_signer {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+                assert_keys_equal(*destination.owner, system_program::id())?;
+                destination
+            }
+            None => dest_account_info,
+        };
         // Checking if amount is greater than allowed amount
         if amount>allowed_amt {
             return Err(ProgramError::InsufficientFunds);
         }
-        // Checking if paused stream is greater than withdraw limit
-        if escrow.paused == 1 && amount > escrow.withdraw_limit {
-            return Err(ProgramError::InsufficientFunds);
-        }
         let (_account_address, bump_seed) = get_master_address_and_bump_seed(
             source_account_info.key,
             program_id,
@@ -301,28 +472,113 @@ impl Processor {
         )?;
         create_transfer(
             pda,
-            dest_account_info,
+            receiver_info,
             system_program,
             receiver_amount,
             pda_signer_seeds
         )?;
-        if escrow.paused == 1{
-            escrow.withdraw_limit -= amount;
-        }
-        escrow.withdrawn += amount;
+        escrow.withdrawn = escrow.withdrawn.checked_add(amount).ok_or(TokenError::Overflow)?;
         // escrow.amount -= amount;
-        // Closing account to send rent to sender
-        if escrow.withdrawn == escrow.amount { 
-            let dest_starting_lamports = source_account_info.lamports();
-            **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(pda_data.lamports())
-                .ok_or(TokenError::Overflow)?;
-            **pda_data.lamports.borrow_mut() = 0;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it
+        if escrow.rate == 0 && escrow.withdrawn == escrow.amount {
+            if let Some(rent_payer_info) = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)? {
+                close_program_account(pda_data, rent_payer_info)?;
+            }
         }
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
         let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
-        withdraw_state.amount = withdraw_state.amount.checked_sub(amount).unwrap();
-        msg!("{:?}",withdraw_state);
+        withdraw_state.amount = withdraw_state.amount.checked_sub(escrow.reserved_part(amount)).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        Ok(())
+    }
+    /// Function for a beneficiary of a split solana stream to withdraw its share. Like solana withdrawals
+    /// it needs no signature unless the stream requires the recipient's.
+    fn process_sol_beneficiary_withdraw(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // stream initiator address
+        let beneficiary_info = next_account_info(account_info_iter)?; // beneficiary receiving its share
+        let pda = next_account_info(account_info_iter)?; // locked fund
+        let pda_data = next_account_info(account_info_iter)?; // stored data 
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data 
+        let system_program = next_account_info(account_info_iter)?; // system program id 
+        let fee_account =  next_account_info(account_info_iter)?; // 0.25 fee account
+        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        if fee_account.key != fee_receiver {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+            PREFIX,
+            source_account_info.key,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let index = escrow.beneficiaries.iter().position(|b| b.address == *beneficiary_info.key).ok_or(TokenError::EscrowMismatch)?;
+        // Beneficiaries stand in for the recipient
+        if escrow.recipient_must_sign && !beneficiary_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        Self::renew_sol_stream(program_id, &mut escrow, pda, withdraw_data, now)?;
+        if now <= escrow.start_time {
+            return Err(TokenError::StreamNotStarted.into());
+        }
+        if now < escrow.cliff_time {
+            return Err(TokenError::CliffNotReached.into());
+        }
+        let amount = escrow.beneficiary_withdrawable(index, now)?;
+        msg!("Beneficiary can withdraw {}",amount);
+        if amount == 0 {
+            return Err(ProgramError::InsufficientFunds);
+        }
+        let (_account_address, bump_seed) = get_master_address_and_bump_seed(
+            source_account_info.key,
+            program_id,
+        );
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        let comission: u64 = 25*amount/10000; 
+        let receiver_amount:u64=amount-comission;
+        create_transfer(
+            pda,
+            fee_account,
+            system_program,
+            comission,
+            pda_signer_seeds
+        )?;
+        create_transfer(
+            pda,
+            beneficiary_info,
+            system_program,
+            receiver_amount,
+            pda_signer_seeds
+        )?;
+        escrow.beneficiaries[index].withdrawn = escrow.beneficiaries[index].withdrawn.checked_add(amount).ok_or(TokenError::Overflow)?;
+        escrow.withdrawn = escrow.withdrawn.checked_add(amount).ok_or(TokenError::Overflow)?;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it once every share is paid
+        if escrow.withdrawn == escrow.amount {
+            if let Some(rent_payer_info) = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)? {
+                close_program_account(pda_data, rent_payer_info)?;
+            }
+        }
+        let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(amount).ok_or(TokenError::Overflow)?;
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
         Ok(())
     }
@@ -341,12 +597,9 @@ impl Processor {
         if fee_account.key != fee_receiver {
             return Err(TokenError::OwnerMismatch.into());
         }
-        if *pda_data.owner != *program_id && *withdraw_data.owner != *program_id{
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
             return Err(ProgramError::InvalidArgument);
         }
-        if !source_account_info.is_signer {
-            return Err(ProgramError::MissingRequiredSignature); 
-        }
         let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
             PREFIX,
             source_account_info.key,
@@ -356,20 +609,38 @@ impl Processor {
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
-        let mut escrow = Stream::try_from_slice(&pda_data.data.borrow())?;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        assert_cancel_allowed(escrow.cancel_policy, source_account_info, dest_account_info)?;
         let now = Clock::get()?.unix_timestamp as u64;
+        Self::renew_sol_stream(program_id, &mut escrow, pda, withdraw_data, now)?;
         // Amount that recipient should receive.  
-        let mut allowed_amt = escrow.allowed_amt(now);
+        let (owed_amt, _) = escrow.cancel_split(now)?;
+        let mut allowed_amt = owed_amt;
+        if escrow.rate != 0 {
+            let withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            allowed_amt = Self::rate_stream_cap(allowed_amt, pda.lamports(), withdraw_state.amount);
+        }
         if now >= escrow.end_time {
             msg!("Stream already completed");
             return Err(TokenError::StreamNotStarted.into());
         }
-        if now < escrow.start_time {
-            allowed_amt = 0;
-        }
         if *source_account_info.key != escrow.sender {
             return Err(TokenError::OwnerMismatch.into());
         }
+        // Split streams stop vesting here, each beneficiary still withdraws what it is owed
+        if !escrow.beneficiaries.is_empty() {
+            let released = escrow.end_now(now, false)?;
+            let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            withdraw_state.amount = withdraw_state.amount.checked_sub(released).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+            escrow.save(pda_data)?;
+            return Ok(());
+        }
         let (_account_address, bump_seed) = get_master_address_and_bump_seed(
             source_account_info.key,
             program_id,
@@ -378,7 +649,6 @@ impl Processor {
             &source_account_info.key.to_bytes(),
             &[bump_seed],
         ];
-        allowed_amt = allowed_amt.checked_sub(escrow.withdrawn).unwrap();
         let comission: u64 = 25*allowed_amt/10000; 
         let receiver_amount:u64=allowed_amt-comission;
         create_transfer(
@@ -397,18 +667,24 @@ impl Processor {
             pda_signer_seeds
         )?;
         let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
-        withdraw_state.amount -= escrow.amount.checked_sub(escrow.withdrawn).unwrap();
+        withdraw_state.amount = withdraw_state.amount.checked_sub(escrow.amount.saturating_sub(escrow.withdrawn)).ok_or(TokenError::Overflow)?;
+        // Open-ended stream the vault could not pay in full stays open with its debt reserved
+        if allowed_amt < owed_amt {
+            let debt = escrow.keep_rate_debt(now, owed_amt, allowed_amt)?;
+            msg!("Stream debt: {}", debt);
+            withdraw_state.amount = withdraw_state.amount.checked_add(debt).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+            escrow.save(pda_data)?;
+            return Ok(());
+        }
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
         // We don't need to send remaining funds to sender, its already in sender master pda account which he can withdraw with withdraw function
-        // Closing account to send rent to sender
-        let dest_starting_lamports = source_account_info.lamports();
-        **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-            .checked_add(pda_data.lamports())
-            .ok_or(TokenError::Overflow)?;
-
-        **pda_data.lamports.borrow_mut() = 0;
         escrow.amount = 0;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it
+        let rent_payer_info = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)?
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        close_program_account(pda_data, rent_payer_info)?;
         Ok(())
     }
     //Function to pause solana stream
@@ -421,9 +697,9 @@ impl Processor {
         if *pda_data.owner != *program_id {
             return Err(ProgramError::InvalidArgument);
         }
-        let mut escrow = Stream::try_from_slice(&pda_data.data.borrow())?;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
         let now = Clock::get()?.unix_timestamp as u64;
-        let allowed_amt = escrow.allowed_amt(now);
         if now >= escrow.end_time {
             return Err(TokenError::TimeEnd.into());
         }
@@ -433,22 +709,15 @@ impl Processor {
         if escrow.start_time >= escrow.end_time {
             return Err(TokenError::InvalidInstruction.into());
         }
-        // Both sender and receiver can pause / resume stream
-        if !source_account_info.is_signer && !dest_account_info.is_signer{ 
-            return Err(ProgramError::MissingRequiredSignature); 
-        }
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
 
-        if *source_account_info.key != escrow.sender || *dest_account_info.key != escrow.recipient { 
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+
+        if *source_account_info.key != escrow.sender {
             return Err(TokenError::EscrowMismatch.into());
         }
-        if escrow.paused ==1{
-            return Err(TokenError::AlreadyPaused.into());
-        }
-        escrow.paused = 1;
-        escrow.withdraw_limit = allowed_amt;
-        escrow.paused_at = now;
-        msg!("{:?}",escrow);
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.pause(now)?;
+        escrow.save(pda_data)?;
         Ok(())
     }
     //Function to resume solana stream
@@ -461,221 +730,1850 @@ impl Processor {
             return Err(ProgramError::InvalidArgument);
         }
         let now = Clock::get()?.unix_timestamp as u64;
-        let mut escrow = Stream::try_from_slice(&pda_data.data.borrow())?;
-        // Both sender and receiver can pause / resume stream
-        if !source_account_info.is_signer && !dest_account_info.is_signer{ 
-            return Err(ProgramError::MissingRequiredSignature); 
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
         }
-        if *source_account_info.key != escrow.sender || *dest_account_info.key != escrow.recipient {
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        if *source_account_info.key != escrow.sender {
             return Err(TokenError::EscrowMismatch.into());
         }
-        if escrow.paused ==0{
-            return Err(TokenError::AlreadyResumed.into());
-        }
-        let time_spent = now - escrow.paused_at;
-        let paused_start_time = escrow.start_time + time_spent;
-        let paused_amount = escrow.allowed_amt(paused_start_time);
-        let current_amount = escrow.allowed_amt(now);
-        let total_amount_to_sent = current_amount - paused_amount;
-        msg!("total_amount_to_sent: {},  paused_amount:{}, current_amount:{}",total_amount_to_sent,paused_amount,current_amount);
-        escrow.withdrawn = escrow.withdrawn + total_amount_to_sent;
-        escrow.paused = 0;
-        escrow.paused_at = 0;
-        msg!("{:?}",escrow);
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        if escrow.pause_forfeits {
+            let withdraw_data = next_account_info(account_info_iter)?; // withdraw data
+            let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+                PREFIX,
+                &escrow.sender,
+                program_id,
+            );
+            assert_keys_equal(*withdraw_data.key,account_address )?;
+            let forfeited = escrow.resume_forfeit(now)?;
+            let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            withdraw_state.amount = withdraw_state.amount.checked_sub(forfeited).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        } else {
+            escrow.resume(now)?;
+        }
+        escrow.save(pda_data)?;
         Ok(())
     }
-    // Function to initialize token streaming 
-    fn process_token_stream(program_id: &Pubkey, accounts: &[AccountInfo], start_time: u64, end_time: u64, amount: u64) -> ProgramResult {
+    //Function for the arbiter to freeze a disputed solana stream
+    fn process_freeze_sol_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let source_account_info = next_account_info(account_info_iter)?;  // sender 
-        let dest_account_info = next_account_info(account_info_iter)?; // recipient
-        let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
-        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
-        let token_program_info = next_account_info(account_info_iter)?; // TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA
-        let system_program = next_account_info(account_info_iter)?; // system address
-        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
-
-        // Get the rent sysvar via syscall
-        let rent = Rent::get()?; //
-        if token_program_info.key != &spl_token::id() {
-            return Err(ProgramError::IncorrectProgramId);
-        }    
-        // Since we are performing system_instruction source account must be signer
-        if !source_account_info.is_signer {
+        let arbiter_info = next_account_info(account_info_iter)?; // arbiter of the stream
+        let pda_data = next_account_info(account_info_iter)?;
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if !arbiter_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        // current time in unix time
-        let now = Clock::get()?.unix_timestamp as u64; 
-        if now >= end_time{
-            return Err(TokenError::TimeEnd.into());
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.arbiter == Pubkey::default() || escrow.arbiter != *arbiter_info.key {
+            return Err(TokenError::OwnerMismatch.into());
         }
-        if start_time >= end_time {
-            return Err(TokenError::InvalidInstruction.into());
+        escrow.freeze(now)?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function for the arbiter to settle a frozen solana stream
+    fn process_resolve_sol_stream(program_id: &Pubkey,accounts: &[AccountInfo],release_to_recipient: bool) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let arbiter_info = next_account_info(account_info_iter)?; // arbiter of the stream
+        let pda_data = next_account_info(account_info_iter)?;
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data of the sender
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
         }
-        let space_size = std::mem::size_of::<StreamToken>();
-
-        let (account_address, bump_seed) = get_token_withdraw_data_and_bump_seed(
-            PREFIX_TOKEN,
-            source_account_info.key,
-            token_mint_info.key,
+        if !arbiter_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.arbiter == Pubkey::default() || escrow.arbiter != *arbiter_info.key {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+            PREFIX,
+            &escrow.sender,
             program_id,
         );
         assert_keys_equal(*withdraw_data.key,account_address )?;
-        let withdraw_data_signer_seeds: &[&[_]] = &[
-            PREFIX_TOKEN.as_bytes(),
-            &source_account_info.key.to_bytes(),
-            &token_mint_info.key.to_bytes(),
-            &[bump_seed],
-        ];
-        if withdraw_data.data_is_empty(){
-            let transfer_amount =  rent.minimum_balance(std::mem::size_of::<TokenWithdraw>());
-            create_pda_account_signed(
-                source_account_info,
-                transfer_amount,
-                std::mem::size_of::<TokenWithdraw>(),
-                program_id,
-                system_program,
-                withdraw_data,
-                withdraw_data_signer_seeds
-            )?;
-        }
-        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
-        withdraw_state.amount += amount;
+        // Funds released to the sender stay in its master pda, they are only no longer reserved
+        let released = escrow.resolve(now, release_to_recipient)?;
+        let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(released).ok_or(TokenError::Overflow)?;
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
-
-        if !pda_data.data_is_empty(){
-            return Err(TokenError::StreamAlreadyCreated.into());
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function to schedule a pause window on a solana stream
+    fn process_schedule_sol_pause(program_id: &Pubkey,accounts: &[AccountInfo],pause_start: u64,pause_end: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let dest_account_info = next_account_info(account_info_iter)?; // recipient
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::EscrowMismatch.into());
         }
-        let transfer_amount =  rent.minimum_balance(std::mem::size_of::<StreamToken>());
+        // Stream resumes by itself at pause_end, nobody has to be online
+        escrow.schedule_pause(now, pause_start, pause_end)?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function to edit the metadata of a solana stream before it starts
+    fn process_update_sol_metadata(program_id: &Pubkey,accounts: &[AccountInfo],metadata: StreamMetadata) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
 
-        create_pda_account( 
-            source_account_info,
-            transfer_amount,
-            space_size,
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !source_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        if now >= escrow.start_time {
+            return Err(TokenError::MetadataLocked.into());
+        }
+        assert_valid_metadata(&metadata)?;
+        escrow.metadata = metadata;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function to close a finished solana stream and return its rent
+    fn process_close_sol_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?; // sender or recipient
+        let rent_payer_info = next_account_info(account_info_iter)?; // account that paid the stream rent
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !authority_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        if !Stream::is_kind(pda_data) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if *authority_info.key != escrow.sender {
+            assert_stream_payee(authority_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        }
+        // Only the sender can end a subscription that could still roll over
+        if escrow.renewable && *authority_info.key != escrow.sender {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        let rent_payer = rent_refund_address(&escrow.rent_payer, &escrow.sender);
+        assert_keys_equal(rent_payer, *rent_payer_info.key)?;
+        if escrow.rate != 0 || escrow.withdrawn < escrow.amount {
+            return Err(TokenError::StreamNotFinished.into());
+        }
+        close_program_account(pda_data, rent_payer_info)?;
+        // Sender can pass its master pda and withdraw data to close the withdraw data too. It is only
+        // closed when nothing is reserved and the vault is empty, the next stream sets it up again.
+        if let Ok(pda) = next_account_info(account_info_iter) {
+            let withdraw_data = next_account_info(account_info_iter)?; // withdraw data of the sender
+            if *authority_info.key != escrow.sender {
+                return Err(TokenError::OwnerMismatch.into());
+            }
+            if *withdraw_data.owner != *program_id {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let (account_address, _bump_seed) = get_master_address_and_bump_seed(
+                authority_info.key,
+                program_id,
+            );
+            assert_keys_equal(*pda.key,account_address )?;
+            let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+                PREFIX,
+                authority_info.key,
+                program_id,
+            );
+            assert_keys_equal(*withdraw_data.key,account_address )?;
+            let withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            if withdraw_state.amount == 0 && pda.lamports() == 0 {
+                close_program_account(withdraw_data, authority_info)?;
+            } else {
+                msg!("Withdraw data still in use");
+            }
+        }
+        Ok(())
+    }
+    //Function to hand over a solana stream to a new recipient
+    fn process_update_sol_recipient(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let dest_account_info = next_account_info(account_info_iter)?; // current recipient
+        let new_recipient_info = next_account_info(account_info_iter)?; // new recipient
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        // Only the current recipient can give away the stream
+        if !dest_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        if *dest_account_info.key != escrow.recipient {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        // Tokenized streams change hands by transferring the receivable token
+        if escrow.receivable_mint != Pubkey::default() {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        if escrow.rate == 0 && escrow.withdrawn == escrow.amount {
+            msg!("Stream already completed");
+            return Err(TokenError::TimeEnd.into());
+        }
+        escrow.assert_valid_new_party(new_recipient_info.key)?;
+        // Vested but unwithdrawn funds move along with the stream
+        escrow.recipient = *new_recipient_info.key;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function to move a solana stream and its locked funds to a new sender
+    fn process_update_sol_sender(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // current sender
+        let new_sender_info = next_account_info(account_info_iter)?; // new sender
+        let pda = next_account_info(account_info_iter)?; // master pda of current sender
+        let new_pda = next_account_info(account_info_iter)?; // master pda of new sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data of current sender
+        let new_withdraw_data = next_account_info(account_info_iter)?; // withdraw data of new sender
+        let system_program = next_account_info(account_info_iter)?; // system program id
+
+        let rent = Rent::get()?;
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        // New sender takes over the stream's obligations so it has to agree to the move
+        if !source_account_info.is_signer || !new_sender_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        assert_keys_equal(system_program::id(), *system_program.key)?;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        escrow.assert_valid_new_party(new_sender_info.key)?;
+        // Rent stays with whoever paid it. Legacy layouts cannot record the payer, their rent goes to
+        // the current sender.
+        if escrow.rent_payer == Pubkey::default() && !Stream::is_legacy(pda_data) {
+            escrow.rent_payer = escrow.sender;
+        }
+        let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+            PREFIX,
+            source_account_info.key,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        let (new_account_address, new_bump_seed) = get_withdraw_data_and_bump_seed(
+            PREFIX,
+            new_sender_info.key,
+            program_id,
+        );
+        assert_keys_equal(*new_withdraw_data.key,new_account_address )?;
+        let (account_address, bump_seed) = get_master_address_and_bump_seed(
+            source_account_info.key,
+            program_id,
+        );
+        assert_keys_equal(*pda.key,account_address )?;
+        let (new_account_address, _new_bump_seed) = get_master_address_and_bump_seed(
+            new_sender_info.key,
             program_id,
+        );
+        assert_keys_equal(*new_pda.key,new_account_address )?;
+        // Funds that are still locked in the stream
+        let remaining_amt = escrow.amount.saturating_sub(escrow.withdrawn);
+        if remaining_amt == 0 && escrow.rate == 0 {
+            msg!("Stream already completed");
+            return Err(TokenError::TimeEnd.into());
+        }
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        create_transfer(
+            pda,
+            new_pda,
             system_program,
-            pda_data
+            remaining_amt,
+            pda_signer_seeds
         )?;
-        let mut escrow = StreamToken::try_from_slice(&pda_data.data.borrow())?;
-        escrow.start_time = start_time;
-        escrow.end_time = end_time;
-        escrow.paused = 0;
-        escrow.withdraw_limit = 0;
-        escrow.sender = *source_account_info.key;
-        escrow.recipient = *dest_account_info.key;
-        escrow.amount = amount;
-        escrow.token_mint = *token_mint_info.key;
-        escrow.withdrawn = 0;
-        escrow.paused_at = 0;
-        msg!("{:?}",escrow);
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
-        msg!("{}",pda_data.data_len());
+        let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(remaining_amt).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        let new_withdraw_data_signer_seeds: &[&[_]] = &[
+            PREFIX.as_bytes(),
+            &new_sender_info.key.to_bytes(),
+            &[new_bump_seed],
+        ];
+        if new_withdraw_data.data_is_empty(){
+            let transfer_amount =  rent.minimum_balance(std::mem::size_of::<Withdraw>());
+            create_pda_account_signed(
+                source_account_info,
+                transfer_amount,
+                std::mem::size_of::<Withdraw>(),
+                program_id,
+                system_program,
+                new_withdraw_data,
+                new_withdraw_data_signer_seeds
+            )?;
+        }
+        let mut new_withdraw_state = Withdraw::try_from_slice(&new_withdraw_data.data.borrow())?;
+        new_withdraw_state.amount = new_withdraw_state.amount.checked_add(remaining_amt).ok_or(TokenError::Overflow)?;
+        new_withdraw_state.serialize(&mut &mut new_withdraw_data.data.borrow_mut()[..])?;
+        escrow.sender = *new_sender_info.key;
+        escrow.save(pda_data)?;
         Ok(())
     }
-    // Function to withdraw from  token streaming 
-    fn process_token_withdraw_stream_deprecated(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    //Function to add funds to a running sol stream
+    fn process_top_up_sol_stream(program_id: &Pubkey,accounts: &[AccountInfo],amount: u64,extend_end: bool) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data
+
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !source_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+            PREFIX,
+            source_account_info.key,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        escrow.top_up(now, amount, extend_end)?;
+        let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_add(amount).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function to lower the amount of a running sol stream
+    fn process_reduce_sol_stream(program_id: &Pubkey,accounts: &[AccountInfo],amount: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data
+
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !source_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        // Lowering the amount is a partial cancel
+        if escrow.cancel_policy != CANCEL_BY_SENDER && escrow.cancel_policy != CANCEL_BY_BOTH {
+            return Err(TokenError::CancelNotAllowed.into());
+        }
+        let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+            PREFIX,
+            source_account_info.key,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        let released = escrow.reduce_amount(now, amount)?;
+        let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(released).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    // Function to initialize token streaming 
+    fn process_token_stream(program_id: &Pubkey, accounts: &[AccountInfo], start_time: u64, end_time: u64, amount: u64) -> ProgramResult {
+        let data = StreamTerms {
+            start_time,
+            end_time,
+            amount,
+            ..Default::default()
+        };
+        Self::process_token_vesting_stream(program_id, accounts, data)
+    }
+    // Function to initialize token streaming with a custom vesting schedule
+    fn process_token_vesting_stream(program_id: &Pubkey, accounts: &[AccountInfo], data: StreamTerms) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let source_account_info = next_account_info(account_info_iter)?;  // sender 
         let dest_account_info = next_account_info(account_info_iter)?; // recipient
-        let pda = next_account_info(account_info_iter)?; // master pda
         let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
         let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
-        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
+        let token_program_info = next_account_info(account_info_iter)?; // TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA
+        let system_program = next_account_info(account_info_iter)?; // system address
         let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
-        let rent_info = next_account_info(account_info_iter)?; // rent address
-        let pda_associated_info = next_account_info(account_info_iter)?; // Associated token of pda
-        let receiver_associated_info = next_account_info(account_info_iter)?; // Associated token of receiver
+
+        // Get the rent sysvar via syscall
+        let rent = Rent::get()?; //
+        if token_program_info.key != &spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }    
+        // Since we are performing system_instruction source account must be signer
+        if !source_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        // Stream lives at an address clients can recompute from its parties
+        let seeds = match data.nonce {
+            Some(nonce) => StreamSeeds {
+                sender: *source_account_info.key,
+                recipient: *dest_account_info.key,
+                nonce,
+                bump: 0,
+            },
+            None => StreamSeeds::default(),
+        };
+        let mut escrow = StreamToken {
+            start_time: data.start_time,
+            end_time: data.end_time,
+            amount: data.amount,
+            sender: *source_account_info.key,
+            recipient: *dest_account_info.key,
+            token_mint: *token_mint_info.key,
+            cliff_time: data.cliff_time,
+            cliff_amount: data.cliff_amount,
+            release_interval: data.release_interval,
+            breakpoints: data.breakpoints,
+            crank_tip: data.crank_tip,
+            crank_interval: data.crank_interval,
+            renewable: data.renewable,
+            periods: 1,
+            rate: data.rate,
+            cancel_policy: data.cancel_policy,
+            pause_policy: data.pause_policy,
+            pause_forfeits: data.pause_forfeits,
+            metadata: data.metadata,
+            seeds,
+            rent_payer: *source_account_info.key,
+            beneficiaries: data.beneficiaries,
+            recipient_must_sign: data.recipient_must_sign,
+            arbiter: data.arbiter,
+            ..Default::default()
+        };
+        // Receivable mint is derived from the stream address and created with the stream
+        if data.tokenized {
+            let (mint_address, _bump_seed) = get_receivable_mint_and_bump_seed(
+                PREFIX_RECEIVABLE,
+                pda_data.key,
+                program_id,
+            );
+            escrow.receivable_mint = mint_address;
+        }
+        // current time in unix time
+        let now = Clock::get()?.unix_timestamp as u64; 
+        escrow.assert_valid_terms(source_account_info.key, dest_account_info.key, now)?;
+        escrow.end_time = escrow.effective_end_time();
+        // Account is sized to the serialized stream so it grows with the breakpoints
+        let space_size = escrow.account_len()?;
+
+        let (account_address, bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            source_account_info.key,
+            token_mint_info.key,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        let withdraw_data_signer_seeds: &[&[_]] = &[
+            PREFIX_TOKEN.as_bytes(),
+            &source_account_info.key.to_bytes(),
+            &token_mint_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        if withdraw_data.data_is_empty(){
+            let transfer_amount =  rent.minimum_balance(std::mem::size_of::<TokenWithdraw>());
+            create_pda_account_signed(
+                source_account_info,
+                transfer_amount,
+                std::mem::size_of::<TokenWithdraw>(),
+                program_id,
+                system_program,
+                withdraw_data,
+                withdraw_data_signer_seeds
+            )?;
+        }
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount += escrow.amount;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+
+        if !pda_data.data_is_empty(){
+            return Err(TokenError::StreamAlreadyCreated.into());
+        }
+        escrow.seeds = create_stream_account(
+            source_account_info,
+            token_mint_info.key,
+            escrow.seeds,
+            space_size,
+            program_id,
+            system_program,
+            pda_data
+        )?;
+        if data.tokenized {
+            create_receivable_mint(program_id, pda_data.key, source_account_info, dest_account_info, system_program, account_info_iter)?;
+        }
+        escrow.init(pda_data)?;
+        msg!("{}",pda_data.data_len());
+        Ok(())
+    }
+    /// Function to initialize several solana streams with a single reservation update
+    fn process_sol_batch_stream(program_id: &Pubkey, accounts: &[AccountInfo], streams: Vec<BatchStream>) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;  //sender
+        let withdraw_data = next_account_info(account_info_iter)?; // pda data storage
+        let system_program = next_account_info(account_info_iter)?; // system program
+        // followed by one stream data account per entry
+        let rent = Rent::get()?;
+        if !source_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        assert_keys_equal(system_program::id(), *system_program.key)?;
+        let now = Clock::get()?.unix_timestamp as u64;
+        if streams.is_empty() || streams.len() > MAX_BATCH_STREAMS {
+            return Err(TokenError::InvalidInstruction.into());
+        }
+        if accounts.len() < 3 + streams.len() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        // Every entry is checked before any account is touched
+        let mut escrows = Vec::with_capacity(streams.len());
+        let mut total_amount: u64 = 0;
+        for (i, entry) in streams.into_iter().enumerate() {
+            let seeds = match entry.nonce {
+                Some(nonce) => StreamSeeds {
+                    sender: *source_account_info.key,
+                    recipient: entry.recipient,
+                    nonce,
+                    bump: 0,
+                },
+                None => StreamSeeds::default(),
+            };
+            let escrow = Stream {
+                start_time: entry.start_time,
+                end_time: entry.end_time,
+                amount: entry.amount,
+                sender: *source_account_info.key,
+                recipient: entry.recipient,
+                periods: 1,
+                seeds,
+                rent_payer: *source_account_info.key,
+                ..Default::default()
+            };
+            if let Err(err) = escrow.assert_valid_terms(source_account_info.key, &entry.recipient, now) {
+                msg!("Batch entry {}: invalid stream terms", i);
+                return Err(err);
+            }
+            total_amount = total_amount.checked_add(entry.amount).ok_or(TokenError::Overflow)?;
+            escrows.push(escrow);
+        }
+        let (account_address, bump_seed) = get_withdraw_data_and_bump_seed(
+            PREFIX,
+            source_account_info.key,
+            program_id,
+        );
+        let withdraw_data_signer_seeds: &[&[_]] = &[
+            PREFIX.as_bytes(),
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        if withdraw_data.data_is_empty(){
+            let transfer_amount =  rent.minimum_balance(std::mem::size_of::<Withdraw>());
+            create_pda_account_signed(
+                source_account_info,
+                transfer_amount,
+                std::mem::size_of::<Withdraw>(),
+                program_id,
+                system_program,
+                withdraw_data,
+                withdraw_data_signer_seeds
+            )?;
+        }
+        let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_add(total_amount).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        for (i, mut escrow) in escrows.into_iter().enumerate() {
+            let pda_data = next_account_info(account_info_iter)?; // stream data storage
+            if !pda_data.data_is_empty(){
+                msg!("Batch entry {}: stream account already exists", i);
+                return Err(TokenError::StreamAlreadyCreated.into());
+            }
+            let space_size = escrow.account_len()?;
+            escrow.seeds = create_stream_account(
+                source_account_info,
+                &Pubkey::default(),
+                escrow.seeds,
+                space_size,
+                program_id,
+                system_program,
+                pda_data
+            )?;
+            escrow.init(pda_data)?;
+        }
+        Ok(())
+    }
+    /// Function to initialize several token streams with a single reservation update
+    fn process_token_batch_stream(program_id: &Pubkey, accounts: &[AccountInfo], streams: Vec<BatchStream>) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;  // sender 
+        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
+        let token_program_info = next_account_info(account_info_iter)?; // TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA
+        let system_program = next_account_info(account_info_iter)?; // system address
+        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
+        // followed by one stream data account per entry
+        let rent = Rent::get()?;
+        if token_program_info.key != &spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }    
+        if !source_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        assert_keys_equal(system_program::id(), *system_program.key)?;
+        let now = Clock::get()?.unix_timestamp as u64;
+        if streams.is_empty() || streams.len() > MAX_BATCH_STREAMS {
+            return Err(TokenError::InvalidInstruction.into());
+        }
+        if accounts.len() < 5 + streams.len() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        // Every entry is checked before any account is touched
+        let mut escrows = Vec::with_capacity(streams.len());
+        let mut total_amount: u64 = 0;
+        for (i, entry) in streams.into_iter().enumerate() {
+            let seeds = match entry.nonce {
+                Some(nonce) => StreamSeeds {
+                    sender: *source_account_info.key,
+                    recipient: entry.recipient,
+                    nonce,
+                    bump: 0,
+                },
+                None => StreamSeeds::default(),
+            };
+            let escrow = StreamToken {
+                start_time: entry.start_time,
+                end_time: entry.end_time,
+                amount: entry.amount,
+                sender: *source_account_info.key,
+                recipient: entry.recipient,
+                periods: 1,
+                seeds,
+                rent_payer: *source_account_info.key,
+                token_mint: *token_mint_info.key,
+                ..Default::default()
+            };
+            if let Err(err) = escrow.assert_valid_terms(source_account_info.key, &entry.recipient, now) {
+                msg!("Batch entry {}: invalid stream terms", i);
+                return Err(err);
+            }
+            total_amount = total_amount.checked_add(entry.amount).ok_or(TokenError::Overflow)?;
+            escrows.push(escrow);
+        }
+        let (account_address, bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            source_account_info.key,
+            token_mint_info.key,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        let withdraw_data_signer_seeds: &[&[_]] = &[
+            PREFIX_TOKEN.as_bytes(),
+            &source_account_info.key.to_bytes(),
+            &token_mint_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        if withdraw_data.data_is_empty(){
+            let transfer_amount =  rent.minimum_balance(std::mem::size_of::<TokenWithdraw>());
+            create_pda_account_signed(
+                source_account_info,
+                transfer_amount,
+                std::mem::size_of::<TokenWithdraw>(),
+                program_id,
+                system_program,
+                withdraw_data,
+                withdraw_data_signer_seeds
+            )?;
+        }
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_add(total_amount).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        for (i, mut escrow) in escrows.into_iter().enumerate() {
+            let pda_data = next_account_info(account_info_iter)?; // stream data storage
+            if !pda_data.data_is_empty(){
+                msg!("Batch entry {}: stream account already exists", i);
+                return Err(TokenError::StreamAlreadyCreated.into());
+            }
+            let space_size = escrow.account_len()?;
+            escrow.seeds = create_stream_account(
+                source_account_info,
+                token_mint_info.key,
+                escrow.seeds,
+                space_size,
+                program_id,
+                system_program,
+                pda_data
+            )?;
+            escrow.init(pda_data)?;
+        }
+        Ok(())
+    }
+    // Function to withdraw from  token streaming 
+    fn process_token_withdraw_stream_deprecated(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;  // sender 
+        let dest_account_info = next_account_info(account_info_iter)?; // recipient
+        let pda = next_account_info(account_info_iter)?; // master pda
+        let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
+        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
+        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
+        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
+        let rent_info = next_account_info(account_info_iter)?; // rent address
+        let pda_associated_info = next_account_info(account_info_iter)?; // Associated token of pda
+        let receiver_associated_info = next_account_info(account_info_iter)?; // Associated token of receiver
+        let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
+        let system_program = next_account_info(account_info_iter)?;
+        let fee_account = next_account_info(account_info_iter)?;
+        let associated_fee_account = next_account_info(account_info_iter)?;
+
+        if *pda_data.owner != *program_id && *withdraw_data.owner != *program_id {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        if fee_account.key != fee_receiver {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        if token_program_info.key != &spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }    
+        // Since we are performing system_instruction source account must be signer
+        if !dest_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let mut escrow = TokenEscrow::try_from_slice(&pda_data.data.borrow())?;
+        assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
+        let now = Clock::get()?.unix_timestamp as u64;
+        if now <= escrow.start_time {
+            msg!("Stream has not been started");
+            return Err(TokenError::StreamNotStarted.into());
+        }
+        // Recipient can only withdraw the money that is already streamed. 
+        let allowed_amt = escrow.withdrawable_amount(now)?;
+        // let rent = &Rent::from_account_info(dest_account_info)?;
+        msg!("{} allowed_amt",allowed_amt);
+        if *dest_account_info.key != escrow.recipient {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        if amount>allowed_amt {
+            msg!("{} is not yet streamlined.",amount);
+            return Err(ProgramError::InsufficientFunds);
+        }
+        msg!("{}",amount);
+
+        let (account_address, bump_seed) = get_master_address_and_bump_seed(
+            source_account_info.key,
+            program_id,
+        );
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        let pda_associated_token = spl_associated_token_account::get_associated_token_address(&account_address,&escrow.token_mint);
+        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+        if receiver_associated_info.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    dest_account_info.key,
+                    dest_account_info.key,
+                    token_mint_info.key,
+                ),&[
+                    dest_account_info.clone(),
+                    receiver_associated_info.clone(),
+                    dest_account_info.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let fee_account_associated_token = spl_associated_token_account::get_associated_token_address(&fee_account.key,&escrow.token_mint);
+        assert_keys_equal(*associated_fee_account.key, fee_account_associated_token)?;
+        if associated_fee_account.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    dest_account_info.key,
+                    fee_account.key,
+                    token_mint_info.key,
+                ),&[
+                    dest_account_info.clone(),
+                    associated_fee_account.clone(),
+                    fee_account.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let comission: u64 = 25*amount/10000; 
+        let receiver_amount:u64=amount-comission;
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                associated_fee_account.key,
+                pda.key,
+                &[pda.key],
+                comission
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                associated_fee_account.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[&pda_signer_seeds],
+        )?;
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                receiver_associated_info.key,
+                pda.key,
+                &[pda.key],
+                receiver_amount
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                receiver_associated_info.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[&pda_signer_seeds],
+        )?;
+        if escrow.paused == 1{
+            msg!("{}{}",escrow.withdraw_limit,amount);
+            escrow.withdraw_limit -= amount;
+        }
+        msg!("{:?}",escrow);
+
+        escrow.amount  -= amount;
+        // Closing account to send rent to sender 
+        if escrow.amount == 0 { 
+            let dest_starting_lamports = source_account_info.lamports();
+            **source_account_info.lamports.borrow_mut() = dest_starting_lamports
+                .checked_add(pda_data.lamports())
+                .ok_or(TokenError::Overflow)?;
+            **pda_data.lamports.borrow_mut() = 0;
+        }
+        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            source_account_info.key,
+            &escrow.token_mint,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount -= amount;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        Ok(())
+    }
+    // Function to withdraw from  token streaming 
+    fn process_token_withdraw_stream(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+        Self::token_withdraw_stream(program_id, accounts, amount, None, None)
+    }
+    /// Function for the recipient to withdraw a token stream to a token account of its choice
+    fn process_token_withdraw_stream_to(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+        let (destination_info, accounts) = accounts.split_last().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        Self::token_withdraw_stream(program_id, accounts, amount, None, Some(destination_info))
+    }
+    // Shared by recipient withdrawals and keeper cranks, the keeper pays for any new accounts
+    fn token_withdraw_stream<'a>(program_id: &Pubkey, accounts: &[AccountInfo<'a>], amount: u64, keeper: Option<&AccountInfo<'a>>, destination: Option<&AccountInfo<'a>>) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;  // sender 
+        let dest_account_info = next_account_info(account_info_iter)?; // recipient
+        let pda = next_account_info(account_info_iter)?; // master pda
+        let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
+        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
+        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
+        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
+        let rent_info = next_account_info(account_info_iter)?; // rent address
+        let pda_associated_info = next_account_info(account_info_iter)?; // Associated token of pda
+        let receiver_associated_info = next_account_info(account_info_iter)?; // Associated token of receiver
+        let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
+        let system_program = next_account_info(account_info_iter)?;
+        let fee_account = next_account_info(account_info_iter)?;
+        let associated_fee_account = next_account_info(account_info_iter)?;
+
+       
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        if fee_account.key != fee_receiver {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        if token_program_info.key != &spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }    
+        // Since we are performing system_instruction source account must be signer
+        let payer = match keeper {
+            Some(keeper) => keeper,
+            None => {
+                if !dest_account_info.is_signer {
+                    return Err(ProgramError::MissingRequiredSignature); 
+                }
+                dest_account_info
+            }
+        };
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            source_account_info.key,
+            &escrow.token_mint,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
+        // Payout comes out of the vault of the stream sender
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let (account_address, _bump_seed) = get_master_address_and_bump_seed(
+            &escrow.sender,
+            program_id,
+        );
+        assert_keys_equal(*pda.key, account_address)?;
+        let pda_associated_token = get_associated_token_address(pda.key,token_mint_info.key);
+        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+        let now = Clock::get()?.unix_timestamp as u64;
+        Self::renew_token_stream(program_id, &mut escrow, pda_associated_info, withdraw_data, now)?;
+        msg!("current time: {:?}",now);
+        if now <= escrow.start_time {
+            msg!("Stream has not been started");
+            return Err(TokenError::StreamNotStarted.into());
+        }
+        if now < escrow.cliff_time {
+            return Err(TokenError::CliffNotReached.into());
+        }
+        // Recipient can only withdraw the money that is already streamed. 
+        let mut allowed_amt = escrow.withdrawable_amount(now)?;
+        if escrow.rate != 0 {
+            let withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            allowed_amt = Self::rate_stream_cap(allowed_amt, get_token_balance(pda_associated_info)?, withdraw_state.amount);
+        }
+        // let rent = &Rent::from_account_info(dest_account_info)?;
+        msg!("{} allowed_amt",allowed_amt);
+        // Recipient can require its signature to decide when it gets paid, keepers cannot crank it
+        if escrow.recipient_must_sign && !dest_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        // Split streams are paid out to each beneficiary separately
+        if !escrow.beneficiaries.is_empty() {
+            msg!("Split streams are withdrawn by their beneficiaries");
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        // Only the recipient can send its payout to another token account of the stream mint
+        let receiver_token_info = match destination {
+            Some(destination) => {
+                if !dest_account_info.is_signer {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+                assert_token_account_mint(destination, &escrow.token_mint)?;
+                destination
+            }
+            None => {
+                let receiver_associated_token = get_associated_token_address(dest_account_info.key,&escrow.token_mint);
+                assert_keys_equal(receiver_associated_token, *receiver_associated_info.key)?;
+                receiver_associated_info
+            }
+        };
+        if amount>allowed_amt {
+            msg!("{} is not yet streamlined.",amount);
+            return Err(ProgramError::InsufficientFunds);
+        }
+        msg!("{}",amount);
+
+        let (account_address, bump_seed) = get_master_address_and_bump_seed(
+            source_account_info.key,
+            program_id,
+        );
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        let pda_associated_token = spl_associated_token_account::get_associated_token_address(&account_address,&escrow.token_mint);
+        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+        if destination.is_none() && receiver_associated_info.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    payer.key,
+                    dest_account_info.key,
+                    token_mint_info.key,
+                ),&[
+                    payer.clone(),
+                    receiver_associated_info.clone(),
+                    dest_account_info.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let fee_account_associated_token = spl_associated_token_account::get_associated_token_address(&fee_account.key,&escrow.token_mint);
+        assert_keys_equal(*associated_fee_account.key, fee_account_associated_token)?;
+        if associated_fee_account.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    payer.key,
+                    fee_account.key,
+                    token_mint_info.key,
+                ),&[
+                    payer.clone(),
+                    associated_fee_account.clone(),
+                    fee_account.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let comission: u64 = 25*amount/10000; 
+        let receiver_amount:u64=amount-comission;
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                associated_fee_account.key,
+                pda.key,
+                &[pda.key],
+                comission
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                associated_fee_account.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[&pda_signer_seeds],
+        )?;
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                receiver_token_info.key,
+                pda.key,
+                &[pda.key],
+                receiver_amount
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                receiver_token_info.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[&pda_signer_seeds],
+        )?;
+        escrow.withdrawn = escrow.withdrawn.checked_add(amount).ok_or(TokenError::Overflow)?;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it
+        if escrow.rate == 0 && escrow.withdrawn == escrow.amount {
+            if let Some(rent_payer_info) = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)? {
+                close_program_account(pda_data, rent_payer_info)?;
+            }
+        }
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(escrow.reserved_part(amount)).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        Ok(())
+    }
+    /// Function for a beneficiary of a split token stream to withdraw its share. Like token withdrawals
+    /// it always needs the beneficiary's signature, which also covers recipient_must_sign.
+    fn process_token_beneficiary_withdraw(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;  // sender 
+        let beneficiary_info = next_account_info(account_info_iter)?; // beneficiary receiving its share
+        let pda = next_account_info(account_info_iter)?; // master pda
+        let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
+        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
+        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
+        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
+        let rent_info = next_account_info(account_info_iter)?; // rent address
+        let pda_associated_info = next_account_info(account_info_iter)?; // Associated token of pda
+        let beneficiary_associated_info = next_account_info(account_info_iter)?; // Associated token of beneficiary
+        let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
+        let system_program = next_account_info(account_info_iter)?;
+        let fee_account = next_account_info(account_info_iter)?;
+        let associated_fee_account = next_account_info(account_info_iter)?;
+
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        if fee_account.key != fee_receiver {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        if token_program_info.key != &spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        // Beneficiary pays for its associated token account
+        if !beneficiary_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            source_account_info.key,
+            &escrow.token_mint,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let index = escrow.beneficiaries.iter().position(|b| b.address == *beneficiary_info.key).ok_or(TokenError::EscrowMismatch)?;
+        let now = Clock::get()?.unix_timestamp as u64;
+        Self::renew_token_stream(program_id, &mut escrow, pda_associated_info, withdraw_data, now)?;
+        if now <= escrow.start_time {
+            msg!("Stream has not been started");
+            return Err(TokenError::StreamNotStarted.into());
+        }
+        if now < escrow.cliff_time {
+            return Err(TokenError::CliffNotReached.into());
+        }
+        let amount = escrow.beneficiary_withdrawable(index, now)?;
+        msg!("Beneficiary can withdraw {}",amount);
+        if amount == 0 {
+            return Err(ProgramError::InsufficientFunds);
+        }
+        let (account_address, bump_seed) = get_master_address_and_bump_seed(
+            source_account_info.key,
+            program_id,
+        );
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        let pda_associated_token = get_associated_token_address(&account_address,&escrow.token_mint);
+        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+        let beneficiary_associated_token = get_associated_token_address(beneficiary_info.key,&escrow.token_mint);
+        assert_keys_equal(beneficiary_associated_token, *beneficiary_associated_info.key)?;
+        if beneficiary_associated_info.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    beneficiary_info.key,
+                    beneficiary_info.key,
+                    token_mint_info.key,
+                ),&[
+                    beneficiary_info.clone(),
+                    beneficiary_associated_info.clone(),
+                    beneficiary_info.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let fee_account_associated_token = get_associated_token_address(fee_account.key,&escrow.token_mint);
+        assert_keys_equal(*associated_fee_account.key, fee_account_associated_token)?;
+        if associated_fee_account.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    beneficiary_info.key,
+                    fee_account.key,
+                    token_mint_info.key,
+                ),&[
+                    beneficiary_info.clone(),
+                    associated_fee_account.clone(),
+                    fee_account.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let comission: u64 = 25*amount/10000; 
+        let receiver_amount:u64=amount-comission;
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                associated_fee_account.key,
+                pda.key,
+                &[pda.key],
+                comission
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                associated_fee_account.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[pda_signer_seeds],
+        )?;
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                beneficiary_associated_info.key,
+                pda.key,
+                &[pda.key],
+                receiver_amount
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                beneficiary_associated_info.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[pda_signer_seeds],
+        )?;
+        escrow.beneficiaries[index].withdrawn = escrow.beneficiaries[index].withdrawn.checked_add(amount).ok_or(TokenError::Overflow)?;
+        escrow.withdrawn = escrow.withdrawn.checked_add(amount).ok_or(TokenError::Overflow)?;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it once every share is paid
+        if escrow.withdrawn == escrow.amount {
+            if let Some(rent_payer_info) = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)? {
+                close_program_account(pda_data, rent_payer_info)?;
+            }
+        }
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(amount).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        Ok(())
+    }
+    /// Function to cancel token streaming
+    fn process_token_cancel_stream(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;  // sender 
+        let dest_account_info = next_account_info(account_info_iter)?; // recipient
+        let pda = next_account_info(account_info_iter)?; // master pda
+        let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
+        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
+        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
+        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
+        let rent_info = next_account_info(account_info_iter)?; // rent address
+        let receiver_associated_info = next_account_info(account_info_iter)?; // Associated token of receiver
+        let pda_associated_info = next_account_info(account_info_iter)?; // pda associated token info 
+        let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
+        let system_program = next_account_info(account_info_iter)?; // system program id
+        let fee_account = next_account_info(account_info_iter)?;
+        let associated_fee_account = next_account_info(account_info_iter)?;
+
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        if fee_account.key != fee_receiver {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            source_account_info.key,
+            &escrow.token_mint,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        assert_cancel_allowed(escrow.cancel_policy, source_account_info, dest_account_info)?;
+        // Whoever cancels pays for the recipient's associated token account
+        let payer = if source_account_info.is_signer { source_account_info } else { dest_account_info };
+        let now = Clock::get()?.unix_timestamp as u64;
+        Self::renew_token_stream(program_id, &mut escrow, pda_associated_info, withdraw_data, now)?;
+
+        // Amount that recipient should receive.  
+        let (owed_amt, _) = escrow.cancel_split(now)?;
+        let mut allowed_amt = owed_amt;
+        if escrow.rate != 0 {
+            let withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            allowed_amt = Self::rate_stream_cap(allowed_amt, get_token_balance(pda_associated_info)?, withdraw_state.amount);
+        }
+        if now >= escrow.end_time {
+            msg!("Stream already completed");
+            return Err(TokenError::TimeEnd.into());
+        }
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        // Split streams stop vesting here, each beneficiary still withdraws what it is owed
+        if !escrow.beneficiaries.is_empty() {
+            let released = escrow.end_now(now, false)?;
+            let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            withdraw_state.amount = withdraw_state.amount.checked_sub(released).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+            escrow.save(pda_data)?;
+            return Ok(());
+        }
+        assert_keys_equal(*token_mint_info.key, escrow.token_mint)?;
+
+        let receiver_associated_account_check = get_associated_token_address(dest_account_info.key,&escrow.token_mint);
+
+        assert_keys_equal(receiver_associated_account_check, *receiver_associated_info.key)?;
+
+        // Sending pending streaming payment to sender 
+        let (account_address, bump_seed) = get_master_address_and_bump_seed(
+            source_account_info.key,
+            program_id,
+        );
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+
+        let pda_associated_token = get_associated_token_address(&account_address,&escrow.token_mint);
+        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+
+        if receiver_associated_info.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    payer.key,
+                    dest_account_info.key,
+                    token_mint_info.key,
+                ),&[
+                    payer.clone(),
+                    receiver_associated_info.clone(),
+                    dest_account_info.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let fee_account_associated_token = spl_associated_token_account::get_associated_token_address(&fee_account.key,&escrow.token_mint);
+        assert_keys_equal(*associated_fee_account.key, fee_account_associated_token)?;
+        if associated_fee_account.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    dest_account_info.key,
+                    fee_account.key,
+                    token_mint_info.key,
+                ),&[
+                    dest_account_info.clone(),
+                    associated_fee_account.clone(),
+                    fee_account.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        msg!("{:?}",allowed_amt);
+        let comission: u64 = 25*allowed_amt/10000; 
+        let receiver_amount:u64=allowed_amt-comission;
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                associated_fee_account.key,
+                pda.key,
+                &[pda.key],
+                comission
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                associated_fee_account.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[&pda_signer_seeds],
+        )?;
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                receiver_associated_info.key,
+                pda.key,
+                &[pda.key],
+                receiver_amount
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                receiver_associated_info.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[&pda_signer_seeds],
+        )?;
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(escrow.amount.saturating_sub(escrow.withdrawn)).ok_or(TokenError::Overflow)?;
+        // Open-ended stream the vault could not pay in full stays open with its debt reserved
+        if allowed_amt < owed_amt {
+            let debt = escrow.keep_rate_debt(now, owed_amt, allowed_amt)?;
+            msg!("Stream debt: {}", debt);
+            withdraw_state.amount = withdraw_state.amount.checked_add(debt).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+            escrow.save(pda_data)?;
+            return Ok(());
+        }
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        // We don't need to send tokens to sender wallet since tokens are already stored in master pda associated token account
+        escrow.amount = 0;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it
+        let rent_payer_info = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)?
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        close_program_account(pda_data, rent_payer_info)?;
+        Ok(())
+    }
+    /// Function to pause token streaming
+    fn process_pause_token_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;
+        let dest_account_info = next_account_info(account_info_iter)?;
+        let pda_data = next_account_info(account_info_iter)?;
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        let now = Clock::get()?.unix_timestamp as u64;
+        if now >= escrow.end_time {
+            return Err(TokenError::TimeEnd.into());
+        }
+        if now < escrow.start_time{
+            return Err(TokenError::StreamNotStarted.into());
+        }
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+        if escrow.start_time >= escrow.end_time {
+            return Err(TokenError::InvalidInstruction.into());
+        }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        escrow.pause(now)?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function to resume token streaming
+    fn process_resume_token_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;
+        let dest_account_info = next_account_info(account_info_iter)?;
+        let pda_data = next_account_info(account_info_iter)?;
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        if escrow.pause_forfeits {
+            let withdraw_data = next_account_info(account_info_iter)?; // withdraw data
+            let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+                PREFIX_TOKEN,
+                &escrow.sender,
+                &escrow.token_mint,
+                program_id,
+            );
+            assert_keys_equal(*withdraw_data.key,account_address )?;
+            let forfeited = escrow.resume_forfeit(now)?;
+            let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            withdraw_state.amount = withdraw_state.amount.checked_sub(forfeited).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        } else {
+            escrow.resume(now)?;
+        }
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function for the arbiter to freeze a disputed token stream
+    fn process_freeze_token_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let arbiter_info = next_account_info(account_info_iter)?; // arbiter of the stream
+        let pda_data = next_account_info(account_info_iter)?;
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if !arbiter_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.arbiter == Pubkey::default() || escrow.arbiter != *arbiter_info.key {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        escrow.freeze(now)?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function for the arbiter to settle a frozen token stream
+    fn process_resolve_token_stream(program_id: &Pubkey,accounts: &[AccountInfo],release_to_recipient: bool) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let arbiter_info = next_account_info(account_info_iter)?; // arbiter of the stream
+        let pda_data = next_account_info(account_info_iter)?;
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data of the sender
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if !arbiter_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.arbiter == Pubkey::default() || escrow.arbiter != *arbiter_info.key {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            &escrow.sender,
+            &escrow.token_mint,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        // Funds released to the sender stay in its master pda token account, they are only no longer reserved
+        let released = escrow.resolve(now, release_to_recipient)?;
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(released).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function to schedule a pause window on a token stream
+    fn process_schedule_token_pause(program_id: &Pubkey,accounts: &[AccountInfo],pause_start: u64,pause_end: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let dest_account_info = next_account_info(account_info_iter)?; // recipient
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        // Stream resumes by itself at pause_end, nobody has to be online
+        escrow.schedule_pause(now, pause_start, pause_end)?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function to edit the metadata of a token stream before it starts
+    fn process_update_token_metadata(program_id: &Pubkey,accounts: &[AccountInfo],metadata: StreamMetadata) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !source_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        if now >= escrow.start_time {
+            return Err(TokenError::MetadataLocked.into());
+        }
+        assert_valid_metadata(&metadata)?;
+        escrow.metadata = metadata;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function to close a finished token stream and return its rent
+    fn process_close_token_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?; // sender or recipient
+        let rent_payer_info = next_account_info(account_info_iter)?; // account that paid the stream rent
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !authority_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        if !StreamToken::is_kind(pda_data) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if *authority_info.key != escrow.sender {
+            assert_stream_payee(authority_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        }
+        // Only the sender can end a subscription that could still roll over
+        if escrow.renewable && *authority_info.key != escrow.sender {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        let rent_payer = rent_refund_address(&escrow.rent_payer, &escrow.sender);
+        assert_keys_equal(rent_payer, *rent_payer_info.key)?;
+        if escrow.rate != 0 || escrow.withdrawn < escrow.amount {
+            return Err(TokenError::StreamNotFinished.into());
+        }
+        close_program_account(pda_data, rent_payer_info)?;
+        // Sender can pass its master pda, withdraw data, vault token account and the token program to close
+        // them too. They are only closed when nothing is reserved and the vault holds no tokens, the next
+        // stream of the mint sets them up again.
+        if let Ok(pda) = next_account_info(account_info_iter) {
+            let withdraw_data = next_account_info(account_info_iter)?; // withdraw data of the sender
+            let pda_associated_info = next_account_info(account_info_iter)?; // Associated token of pda
+            let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
+            if *authority_info.key != escrow.sender {
+                return Err(TokenError::OwnerMismatch.into());
+            }
+            if *withdraw_data.owner != *program_id {
+                return Err(ProgramError::InvalidArgument);
+            }
+            if token_program_info.key != &spl_token::id() {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            let (account_address, bump_seed) = get_master_address_and_bump_seed(
+                authority_info.key,
+                program_id,
+            );
+            assert_keys_equal(*pda.key,account_address )?;
+            let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+                PREFIX_TOKEN,
+                authority_info.key,
+                &escrow.token_mint,
+                program_id,
+            );
+            assert_keys_equal(*withdraw_data.key,account_address )?;
+            let pda_associated_token = get_associated_token_address(pda.key,&escrow.token_mint);
+            assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+            let withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            let vault_empty = pda_associated_info.data_is_empty() || get_token_balance(pda_associated_info)? == 0;
+            if withdraw_state.amount != 0 || !vault_empty {
+                msg!("Withdraw data still in use");
+                return Ok(());
+            }
+            close_program_account(withdraw_data, authority_info)?;
+            if !pda_associated_info.data_is_empty() {
+                let pda_signer_seeds: &[&[_]] = &[
+                    &authority_info.key.to_bytes(),
+                    &[bump_seed],
+                ];
+                invoke_signed(
+                    &spl_token::instruction::close_account(
+                        token_program_info.key,
+                        pda_associated_info.key,
+                        authority_info.key,
+                        pda.key,
+                        &[],
+                    )?,
+                    &[
+                        pda_associated_info.clone(),
+                        authority_info.clone(),
+                        pda.clone(),
+                        token_program_info.clone()
+                    ],
+                    &[pda_signer_seeds],
+                )?;
+            }
+        }
+        Ok(())
+    }
+    /// Function to hand over a token stream to a new recipient
+    fn process_update_token_recipient(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let dest_account_info = next_account_info(account_info_iter)?; // current recipient
+        let new_recipient_info = next_account_info(account_info_iter)?; // new recipient
+        let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
+        let token_program_info = next_account_info(account_info_iter)?; // TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA
+        let token_mint_info = next_account_info(account_info_iter)?; // token mint of the stream
+        let rent_info = next_account_info(account_info_iter)?; // rent address
+        let new_recipient_associated_info = next_account_info(account_info_iter)?; // Associated token of new recipient
         let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
         let system_program = next_account_info(account_info_iter)?;
-        let fee_account = next_account_info(account_info_iter)?;
-        let associated_fee_account = next_account_info(account_info_iter)?;
 
-        if *pda_data.owner != *program_id && *withdraw_data.owner != *program_id {
+        if *pda_data.owner != *program_id{
             return Err(ProgramError::InvalidArgument);
         }
-        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
-        if fee_account.key != fee_receiver {
-            return Err(TokenError::OwnerMismatch.into());
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
         }
         if token_program_info.key != &spl_token::id() {
             return Err(ProgramError::IncorrectProgramId);
-        }    
-        // Since we are performing system_instruction source account must be signer
+        }
+        // Only the current recipient can give away the stream
         if !dest_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        if pda_data.data_is_empty(){
-            return Err(ProgramError::UninitializedAccount);
-        }
-        let mut escrow = TokenEscrow::try_from_slice(&pda_data.data.borrow())?;
-        assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
-        let now = Clock::get()?.unix_timestamp as u64;
-        if now <= escrow.start_time {
-            msg!("Stream has not been started");
-            return Err(TokenError::StreamNotStarted.into());
-        }
-        // Recipient can only withdraw the money that is already streamed. 
-        let mut allowed_amt = escrow.allowed_amt(now);
-        if now >= escrow.end_time {
-            msg!("Stream has been successfully completed");
-            allowed_amt = escrow.amount;
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
         }
-        // let rent = &Rent::from_account_info(dest_account_info)?;
-        msg!("{} allowed_amt",allowed_amt);
         if *dest_account_info.key != escrow.recipient {
             return Err(TokenError::EscrowMismatch.into());
         }
-        if amount>allowed_amt {
-            msg!("{} is not yet streamlined.",amount);
-            return Err(ProgramError::InsufficientFunds);
-        }
-        msg!("{}",amount);
-        if escrow.paused == 1 && amount > escrow.withdraw_limit {
-            msg!("{} is your withdraw limit",escrow.withdraw_limit);
-            return Err(ProgramError::InsufficientFunds);
+        // Tokenized streams change hands by transferring the receivable token
+        if escrow.receivable_mint != Pubkey::default() {
+            return Err(TokenError::EscrowMismatch.into());
         }
-
-        let (account_address, bump_seed) = get_master_address_and_bump_seed(
-            source_account_info.key,
-            program_id,
-        );
-        let pda_signer_seeds: &[&[_]] = &[
-            &source_account_info.key.to_bytes(),
-            &[bump_seed],
-        ];
-        let pda_associated_token = spl_associated_token_account::get_associated_token_address(&account_address,&escrow.token_mint);
-        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
-        if receiver_associated_info.data_is_empty(){
-            invoke(            
-                &spl_associated_token_account::create_associated_token_account(
-                    dest_account_info.key,
-                    dest_account_info.key,
-                    token_mint_info.key,
-                ),&[
-                    dest_account_info.clone(),
-                    receiver_associated_info.clone(),
-                    dest_account_info.clone(),
-                    token_mint_info.clone(),
-                    token_program_info.clone(),
-                    rent_info.clone(),
-                    associated_token_info.clone(),
-                    system_program.clone()
-                ]
-            )?
+        assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
+        if escrow.rate == 0 && escrow.withdrawn == escrow.amount {
+            msg!("Stream already completed");
+            return Err(TokenError::TimeEnd.into());
         }
-        let fee_account_associated_token = spl_associated_token_account::get_associated_token_address(&fee_account.key,&escrow.token_mint);
-        assert_keys_equal(*associated_fee_account.key, fee_account_associated_token)?;
-        if associated_fee_account.data_is_empty(){
+        escrow.assert_valid_new_party(new_recipient_info.key)?;
+        let new_recipient_associated_token = get_associated_token_address(new_recipient_info.key,&escrow.token_mint);
+        assert_keys_equal(new_recipient_associated_token, *new_recipient_associated_info.key)?;
+        // Current recipient pays for the new recipient's token account
+        if new_recipient_associated_info.data_is_empty(){
             invoke(            
                 &spl_associated_token_account::create_associated_token_account(
                     dest_account_info.key,
-                    fee_account.key,
+                    new_recipient_info.key,
                     token_mint_info.key,
                 ),&[
                     dest_account_info.clone(),
-                    associated_fee_account.clone(),
-                    fee_account.clone(),
+                    new_recipient_associated_info.clone(),
+                    new_recipient_info.clone(),
                     token_mint_info.clone(),
                     token_program_info.clone(),
                     rent_info.clone(),
@@ -684,232 +2582,183 @@ impl Processor {
                 ]
             )?
         }
-        let comission: u64 = 25*amount/10000; 
-        let receiver_amount:u64=amount-comission;
-        invoke_signed(
-            &spl_token::instruction::transfer(
-                token_program_info.key,
-                pda_associated_info.key,
-                associated_fee_account.key,
-                pda.key,
-                &[pda.key],
-                comission
-            )?,
-            &[
-                token_program_info.clone(),
-                pda_associated_info.clone(),
-                associated_fee_account.clone(),
-                pda.clone(),
-                system_program.clone()
-            ],&[&pda_signer_seeds],
-        )?;
-        invoke_signed(
-            &spl_token::instruction::transfer(
-                token_program_info.key,
-                pda_associated_info.key,
-                receiver_associated_info.key,
-                pda.key,
-                &[pda.key],
-                receiver_amount
-            )?,
-            &[
-                token_program_info.clone(),
-                pda_associated_info.clone(),
-                receiver_associated_info.clone(),
-                pda.clone(),
-                system_program.clone()
-            ],&[&pda_signer_seeds],
-        )?;
-        if escrow.paused == 1{
-            msg!("{}{}",escrow.withdraw_limit,amount);
-            escrow.withdraw_limit -= amount;
-        }
-        msg!("{:?}",escrow);
-
-        escrow.amount  -= amount;
-        // Closing account to send rent to sender 
-        if escrow.amount == 0 { 
-            let dest_starting_lamports = source_account_info.lamports();
-            **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(pda_data.lamports())
-                .ok_or(TokenError::Overflow)?;
-            **pda_data.lamports.borrow_mut() = 0;
-        }
-        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
-            PREFIX_TOKEN,
-            source_account_info.key,
-            &escrow.token_mint,
-            program_id,
-        );
-        assert_keys_equal(*withdraw_data.key,account_address )?;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
-        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
-        withdraw_state.amount -= amount;
-        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        // Vested but unwithdrawn funds move along with the stream
+        escrow.recipient = *new_recipient_info.key;
+        escrow.save(pda_data)?;
         Ok(())
     }
-    // Function to withdraw from  token streaming 
-    fn process_token_withdraw_stream(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    /// Function to move a token stream and its locked funds to a new sender
+    fn process_update_token_sender(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let source_account_info = next_account_info(account_info_iter)?;  // sender 
-        let dest_account_info = next_account_info(account_info_iter)?; // recipient
-        let pda = next_account_info(account_info_iter)?; // master pda
+        let source_account_info = next_account_info(account_info_iter)?; // current sender
+        let new_sender_info = next_account_info(account_info_iter)?; // new sender
+        let pda = next_account_info(account_info_iter)?; // master pda of current sender
+        let new_pda = next_account_info(account_info_iter)?; // master pda of new sender
         let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
-        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
-        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
-        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data of current sender
+        let new_withdraw_data = next_account_info(account_info_iter)?; // withdraw data of new sender
+        let token_program_info = next_account_info(account_info_iter)?; // TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA
+        let token_mint_info = next_account_info(account_info_iter)?; // token mint of the stream
         let rent_info = next_account_info(account_info_iter)?; // rent address
-        let pda_associated_info = next_account_info(account_info_iter)?; // Associated token of pda
-        let receiver_associated_info = next_account_info(account_info_iter)?; // Associated token of receiver
+        let pda_associated_info = next_account_info(account_info_iter)?; // Associated token of current master pda
+        let new_pda_associated_info = next_account_info(account_info_iter)?; // Associated token of new master pda
         let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
         let system_program = next_account_info(account_info_iter)?;
-        let fee_account = next_account_info(account_info_iter)?;
-        let associated_fee_account = next_account_info(account_info_iter)?;
 
-       
-        if *pda_data.owner != *program_id && *withdraw_data.owner != *program_id {
+        let rent = Rent::get()?;
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
             return Err(ProgramError::InvalidArgument);
         }
-        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
-        if fee_account.key != fee_receiver {
-            return Err(TokenError::OwnerMismatch.into());
-        }
-        if token_program_info.key != &spl_token::id() {
-            return Err(ProgramError::IncorrectProgramId);
-        }    
-        // Since we are performing system_instruction source account must be signer
-        if !dest_account_info.is_signer {
-            return Err(ProgramError::MissingRequiredSignature); 
-        }
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
-        let mut escrow = StreamToken::try_from_slice(&pda_data.data.borrow())?;
-        assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
-        let now = Clock::get()?.unix_timestamp as u64;
-        msg!("current time: {:?}",now);
-        if now <= escrow.start_time {
-            msg!("Stream has not been started");
-            return Err(TokenError::StreamNotStarted.into());
+        if token_program_info.key != &spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
         }
-        // Recipient can only withdraw the money that is already streamed. 
-        let mut allowed_amt = escrow.allowed_amt(now);
-        if now >= escrow.end_time {
-            allowed_amt = escrow.amount;
+        // New sender takes over the stream's obligations so it has to agree to the move
+        if !source_account_info.is_signer || !new_sender_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
         }
-        allowed_amt -=  escrow.withdrawn;
-        // let rent = &Rent::from_account_info(dest_account_info)?;
-        msg!("{} allowed_amt",allowed_amt);
-        if *dest_account_info.key != escrow.recipient {
-            return Err(TokenError::EscrowMismatch.into());
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
         }
-        if amount>allowed_amt {
-            msg!("{} is not yet streamlined.",amount);
-            return Err(ProgramError::InsufficientFunds);
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
         }
-        msg!("{}",amount);
-        if escrow.paused == 1 && amount > escrow.withdraw_limit {
-            msg!("{} is your withdraw limit",escrow.withdraw_limit);
-            return Err(ProgramError::InsufficientFunds);
+        assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
+        escrow.assert_valid_new_party(new_sender_info.key)?;
+        // Rent stays with whoever paid it. Legacy layouts cannot record the payer, their rent goes to
+        // the current sender.
+        if escrow.rent_payer == Pubkey::default() && !StreamToken::is_legacy(pda_data) {
+            escrow.rent_payer = escrow.sender;
         }
-
+        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            source_account_info.key,
+            token_mint_info.key,
+            program_id,
+        );
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        let (new_account_address, new_bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
+            new_sender_info.key,
+            token_mint_info.key,
+            program_id,
+        );
+        assert_keys_equal(*new_withdraw_data.key,new_account_address )?;
         let (account_address, bump_seed) = get_master_address_and_bump_seed(
             source_account_info.key,
             program_id,
         );
-        let pda_signer_seeds: &[&[_]] = &[
-            &source_account_info.key.to_bytes(),
-            &[bump_seed],
-        ];
-        let pda_associated_token = spl_associated_token_account::get_associated_token_address(&account_address,&escrow.token_mint);
+        assert_keys_equal(*pda.key,account_address )?;
+        let (new_account_address, _new_bump_seed) = get_master_address_and_bump_seed(
+            new_sender_info.key,
+            program_id,
+        );
+        assert_keys_equal(*new_pda.key,new_account_address )?;
+        let pda_associated_token = get_associated_token_address(pda.key,&escrow.token_mint);
         assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
-        if receiver_associated_info.data_is_empty(){
-            invoke(            
-                &spl_associated_token_account::create_associated_token_account(
-                    dest_account_info.key,
-                    dest_account_info.key,
-                    token_mint_info.key,
-                ),&[
-                    dest_account_info.clone(),
-                    receiver_associated_info.clone(),
-                    dest_account_info.clone(),
-                    token_mint_info.clone(),
-                    token_program_info.clone(),
-                    rent_info.clone(),
-                    associated_token_info.clone(),
-                    system_program.clone()
-                ]
-            )?
+        let new_pda_associated_token = get_associated_token_address(new_pda.key,&escrow.token_mint);
+        assert_keys_equal(new_pda_associated_token, *new_pda_associated_info.key)?;
+        // Funds that are still locked in the stream
+        let remaining_amt = escrow.amount.saturating_sub(escrow.withdrawn);
+        if remaining_amt == 0 && escrow.rate == 0 {
+            msg!("Stream already completed");
+            return Err(TokenError::TimeEnd.into());
         }
-        let fee_account_associated_token = spl_associated_token_account::get_associated_token_address(&fee_account.key,&escrow.token_mint);
-        assert_keys_equal(*associated_fee_account.key, fee_account_associated_token)?;
-        if associated_fee_account.data_is_empty(){
+        if new_pda_associated_info.data_is_empty(){
             invoke(            
                 &spl_associated_token_account::create_associated_token_account(
-                    dest_account_info.key,
-                    fee_account.key,
+                    source_account_info.key,
+                    new_pda.key,
                     token_mint_info.key,
                 ),&[
-                    dest_account_info.clone(),
-                    associated_fee_account.clone(),
-                    fee_account.clone(),
+                    source_account_info.clone(),
+                    new_pda_associated_info.clone(),
+                    new_pda.clone(),
                     token_mint_info.clone(),
-                    token_program_info.clone(),
-                    rent_info.clone(),
-                    associated_token_info.clone(),
-                    system_program.clone()
-                ]
-            )?
-        }
-        let comission: u64 = 25*amount/10000; 
-        let receiver_amount:u64=amount-comission;
-        invoke_signed(
-            &spl_token::instruction::transfer(
-                token_program_info.key,
-                pda_associated_info.key,
-                associated_fee_account.key,
-                pda.key,
-                &[pda.key],
-                comission
-            )?,
-            &[
-                token_program_info.clone(),
-                pda_associated_info.clone(),
-                associated_fee_account.clone(),
-                pda.clone(),
-                system_program.clone()
-            ],&[&pda_signer_seeds],
-        )?;
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
         invoke_signed(
             &spl_token::instruction::transfer(
                 token_program_info.key,
                 pda_associated_info.key,
-                receiver_associated_info.key,
+                new_pda_associated_info.key,
                 pda.key,
                 &[pda.key],
-                receiver_amount
+                remaining_amt
             )?,
             &[
                 token_program_info.clone(),
                 pda_associated_info.clone(),
-                receiver_associated_info.clone(),
+                new_pda_associated_info.clone(),
                 pda.clone(),
                 system_program.clone()
-            ],&[&pda_signer_seeds],
+            ],&[pda_signer_seeds],
         )?;
-        if escrow.paused == 1{
-            msg!("{}{}",escrow.withdraw_limit,amount);
-            escrow.withdraw_limit -= amount;
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(remaining_amt).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        let new_withdraw_data_signer_seeds: &[&[_]] = &[
+            PREFIX_TOKEN.as_bytes(),
+            &new_sender_info.key.to_bytes(),
+            &token_mint_info.key.to_bytes(),
+            &[new_bump_seed],
+        ];
+        if new_withdraw_data.data_is_empty(){
+            let transfer_amount =  rent.minimum_balance(std::mem::size_of::<TokenWithdraw>());
+            create_pda_account_signed(
+                source_account_info,
+                transfer_amount,
+                std::mem::size_of::<TokenWithdraw>(),
+                program_id,
+                system_program,
+                new_withdraw_data,
+                new_withdraw_data_signer_seeds
+            )?;
         }
-        msg!("{:?}",escrow);
-        escrow.withdrawn += amount;
-        // Closing account to send rent to sender
-        if escrow.withdrawn == escrow.amount { 
-            let dest_starting_lamports = source_account_info.lamports();
-            **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(pda_data.lamports())
-                .ok_or(TokenError::Overflow)?;
-            **pda_data.lamports.borrow_mut() = 0;
+        let mut new_withdraw_state = TokenWithdraw::try_from_slice(&new_withdraw_data.data.borrow())?;
+        new_withdraw_state.amount = new_withdraw_state.amount.checked_add(remaining_amt).ok_or(TokenError::Overflow)?;
+        new_withdraw_state.serialize(&mut &mut new_withdraw_data.data.borrow_mut()[..])?;
+        escrow.sender = *new_sender_info.key;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function to add funds to a running token stream
+    fn process_top_up_token_stream(program_id: &Pubkey,accounts: &[AccountInfo],amount: u64,extend_end: bool) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data
+
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !source_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
+        }
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
         }
         let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
             PREFIX_TOKEN,
@@ -918,88 +2767,210 @@ impl Processor {
             program_id,
         );
         assert_keys_equal(*withdraw_data.key,account_address )?;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.top_up(now, amount, extend_end)?;
         let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
-        msg!("{:?}",withdraw_state);
-        withdraw_state.amount = withdraw_state.amount.checked_sub(amount).unwrap();
-        msg!("{:?}",withdraw_state);
+        withdraw_state.amount = withdraw_state.amount.checked_add(amount).ok_or(TokenError::Overflow)?;
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
         Ok(())
     }
-    /// Function to cancel token streaming
-    fn process_token_cancel_stream(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    //Function to lower the amount of a running token stream
+    fn process_reduce_token_stream(program_id: &Pubkey,accounts: &[AccountInfo],amount: u64) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let source_account_info = next_account_info(account_info_iter)?;  // sender 
-        let dest_account_info = next_account_info(account_info_iter)?; // recipient
-        let pda = next_account_info(account_info_iter)?; // master pda
-        let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
-        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
-        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
-        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
-        let rent_info = next_account_info(account_info_iter)?; // rent address
-        let receiver_associated_info = next_account_info(account_info_iter)?; // Associated token of receiver
-        let pda_associated_info = next_account_info(account_info_iter)?; // pda associated token info 
-        let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
-        let system_program = next_account_info(account_info_iter)?; // system program id
-        let fee_account = next_account_info(account_info_iter)?;
-        let associated_fee_account = next_account_info(account_info_iter)?;
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data
 
-        if *pda_data.owner != *program_id && *withdraw_data.owner != *program_id {
+        if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
             return Err(ProgramError::InvalidArgument);
         }
-        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
-        if fee_account.key != fee_receiver {
-            return Err(TokenError::OwnerMismatch.into());
-        }
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
         if !source_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        let mut escrow = StreamToken::try_from_slice(&pda_data.data.borrow())?;
         let now = Clock::get()?.unix_timestamp as u64;
-
-        // Amount that recipient should receive.  
-        let mut allowed_amt = escrow.allowed_amt(now);
-
-        if now < escrow.start_time {
-            allowed_amt = 0;
-        }
-        if now >= escrow.end_time {
-            msg!("Stream already completed");
-            return Err(TokenError::TimeEnd.into());
+        let mut escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        if escrow.frozen {
+            return Err(TokenError::StreamFrozen.into());
         }
         if *source_account_info.key != escrow.sender {
             return Err(TokenError::OwnerMismatch.into());
         }
-        assert_keys_equal(*token_mint_info.key, escrow.token_mint)?;
-
-        let receiver_associated_account_check = get_associated_token_address(dest_account_info.key,&escrow.token_mint);
-
-        assert_keys_equal(receiver_associated_account_check, *receiver_associated_info.key)?;
-
-        // Sending pending streaming payment to sender 
-        let (account_address, bump_seed) = get_master_address_and_bump_seed(
+        // Lowering the amount is a partial cancel
+        if escrow.cancel_policy != CANCEL_BY_SENDER && escrow.cancel_policy != CANCEL_BY_BOTH {
+            return Err(TokenError::CancelNotAllowed.into());
+        }
+        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+            PREFIX_TOKEN,
             source_account_info.key,
+            &escrow.token_mint,
             program_id,
         );
-        let pda_signer_seeds: &[&[_]] = &[
-            &source_account_info.key.to_bytes(),
-            &[bump_seed],
-        ];
+        assert_keys_equal(*withdraw_data.key,account_address )?;
+        let released = escrow.reduce_amount(now, amount)?;
+        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        withdraw_state.amount = withdraw_state.amount.checked_sub(released).ok_or(TokenError::Overflow)?;
+        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function to withdraw from several solana streams with a single fee transfer
+    fn process_sol_batch_withdraw(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let dest_account_info = next_account_info(account_info_iter)?; // stream receiver
+        let system_program = next_account_info(account_info_iter)?; // system program id 
+        let fee_account =  next_account_info(account_info_iter)?; // 0.25 fee account
+        // followed by (sender, master pda, stream data, withdraw data) for every stream
+        let stream_accounts = account_info_iter.as_slice();
 
-        let pda_associated_token = get_associated_token_address(&account_address,&escrow.token_mint);
-        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        if fee_account.key != fee_receiver {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        // Recipient pays the aggregated fee
+        if !dest_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        assert_keys_equal(system_program::id(), *system_program.key)?;
+        let streams = stream_accounts.chunks_exact(4);
+        if stream_accounts.is_empty() || !streams.remainder().is_empty() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut total_comission: u64 = 0;
+        for (i, stream) in streams.enumerate() {
+            let (source_account_info, pda, pda_data, withdraw_data) = (&stream[0], &stream[1], &stream[2], &stream[3]);
+            if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+                return Err(ProgramError::InvalidArgument);
+            }
+            if !Stream::is_kind(pda_data) {
+                msg!("Batch entry {}: deprecated streams must be withdrawn individually", i);
+                return Err(TokenError::InvalidInstruction.into());
+            }
+            let mut escrow = Stream::load(pda_data)?;
+            assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+            if escrow.frozen {
+                msg!("Batch entry {}: stream is frozen by its arbiter", i);
+                return Err(TokenError::StreamFrozen.into());
+            }
+            if *dest_account_info.key != escrow.recipient || *source_account_info.key != escrow.sender {
+                msg!("Batch entry {}: stream does not belong to this recipient", i);
+                return Err(TokenError::EscrowMismatch.into());
+            }
+            if escrow.receivable_mint != Pubkey::default() {
+                msg!("Batch entry {}: tokenized streams must be withdrawn individually", i);
+                return Err(TokenError::EscrowMismatch.into());
+            }
+            if !escrow.beneficiaries.is_empty() {
+                msg!("Batch entry {}: split streams are withdrawn by their beneficiaries", i);
+                return Err(TokenError::EscrowMismatch.into());
+            }
+            let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+                PREFIX,
+                source_account_info.key,
+                program_id,
+            );
+            assert_keys_equal(*withdraw_data.key,account_address )?;
+            let (account_address, bump_seed) = get_master_address_and_bump_seed(
+                source_account_info.key,
+                program_id,
+            );
+            assert_keys_equal(*pda.key,account_address )?;
+            let renewed = Self::renew_sol_stream(program_id, &mut escrow, pda, withdraw_data, now)?;
+            let mut amount = escrow.withdrawable_amount(now)?;
+            if escrow.rate != 0 {
+                let withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+                amount = Self::rate_stream_cap(amount, pda.lamports(), withdraw_state.amount);
+            }
+            if amount == 0 {
+                msg!("Batch entry {}: nothing to withdraw", i);
+                // The new periods are already reserved, so the rollover has to be kept with them
+                if renewed > 0 {
+                    escrow.save(pda_data)?;
+                }
+                continue;
+            }
+            let pda_signer_seeds: &[&[_]] = &[
+                &source_account_info.key.to_bytes(),
+                &[bump_seed],
+            ];
+            create_transfer(
+                pda,
+                dest_account_info,
+                system_program,
+                amount,
+                pda_signer_seeds
+            )?;
+            total_comission = total_comission.checked_add(25*amount/10000).ok_or(TokenError::Overflow)?;
+            escrow.withdrawn = escrow.withdrawn.checked_add(amount).ok_or(TokenError::Overflow)?;
+            escrow.save(pda_data)?;
+            // Streams the sender paid for return their rent once finished, others wait for the close instruction
+            if escrow.rate == 0 && escrow.withdrawn == escrow.amount && rent_refund_address(&escrow.rent_payer, &escrow.sender) == *source_account_info.key {
+                close_program_account(pda_data, source_account_info)?;
+            }
+            let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            withdraw_state.amount = withdraw_state.amount.checked_sub(escrow.reserved_part(amount)).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        }
+        if total_comission > 0 {
+            invoke(
+                &solana_program::system_instruction::transfer(
+                    dest_account_info.key,
+                    fee_account.key,
+                    total_comission
+                ),
+                &[
+                    dest_account_info.clone(),
+                    fee_account.clone(),
+                    system_program.clone()
+                ],
+            )?;
+        }
+        Ok(())
+    }
+    /// Function to withdraw from several token streams with a single fee transfer
+    fn process_token_batch_withdraw(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let dest_account_info = next_account_info(account_info_iter)?; // recipient
+        let receiver_associated_info = next_account_info(account_info_iter)?; // Associated token of receiver
+        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
+        let token_mint_info = next_account_info(account_info_iter)?; // token of the streams
+        let rent_info = next_account_info(account_info_iter)?; // rent address
+        let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
+        let system_program = next_account_info(account_info_iter)?;
+        let fee_account = next_account_info(account_info_iter)?;
+        let associated_fee_account = next_account_info(account_info_iter)?;
+        // followed by (sender, master pda, stream data, withdraw data, associated token of master pda) for every stream
+        let stream_accounts = account_info_iter.as_slice();
 
+        let fee_receiver= &Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        if fee_account.key != fee_receiver {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        if token_program_info.key != &spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }    
+        // Recipient pays the aggregated fee
+        if !dest_account_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        let streams = stream_accounts.chunks_exact(5);
+        if stream_accounts.is_empty() || !streams.remainder().is_empty() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let receiver_associated_token = get_associated_token_address(dest_account_info.key,token_mint_info.key);
+        assert_keys_equal(receiver_associated_token, *receiver_associated_info.key)?;
         if receiver_associated_info.data_is_empty(){
             invoke(            
                 &spl_associated_token_account::create_associated_token_account(
-                    source_account_info.key,
+                    dest_account_info.key,
                     dest_account_info.key,
                     token_mint_info.key,
                 ),&[
-                    source_account_info.clone(),
+                    dest_account_info.clone(),
                     receiver_associated_info.clone(),
                     dest_account_info.clone(),
                     token_mint_info.clone(),
@@ -1010,7 +2981,103 @@ impl Processor {
                 ]
             )?
         }
-        let fee_account_associated_token = spl_associated_token_account::get_associated_token_address(&fee_account.key,&escrow.token_mint);
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut total_comission: u64 = 0;
+        for (i, stream) in streams.enumerate() {
+            let (source_account_info, pda, pda_data, withdraw_data, pda_associated_info) = (&stream[0], &stream[1], &stream[2], &stream[3], &stream[4]);
+            if *pda_data.owner != *program_id || *withdraw_data.owner != *program_id{
+                return Err(ProgramError::InvalidArgument);
+            }
+            if !StreamToken::is_kind(pda_data) {
+                msg!("Batch entry {}: deprecated streams must be withdrawn individually", i);
+                return Err(TokenError::InvalidInstruction.into());
+            }
+            let mut escrow = StreamToken::load(pda_data)?;
+            assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+            if escrow.frozen {
+                msg!("Batch entry {}: stream is frozen by its arbiter", i);
+                return Err(TokenError::StreamFrozen.into());
+            }
+            if *dest_account_info.key != escrow.recipient || *source_account_info.key != escrow.sender {
+                msg!("Batch entry {}: stream does not belong to this recipient", i);
+                return Err(TokenError::EscrowMismatch.into());
+            }
+            if escrow.token_mint != *token_mint_info.key {
+                msg!("Batch entry {}: stream is for a different token", i);
+                return Err(TokenError::PublicKeyMismatch.into());
+            }
+            if escrow.receivable_mint != Pubkey::default() {
+                msg!("Batch entry {}: tokenized streams must be withdrawn individually", i);
+                return Err(TokenError::EscrowMismatch.into());
+            }
+            if !escrow.beneficiaries.is_empty() {
+                msg!("Batch entry {}: split streams are withdrawn by their beneficiaries", i);
+                return Err(TokenError::EscrowMismatch.into());
+            }
+            let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+                PREFIX_TOKEN,
+                source_account_info.key,
+                token_mint_info.key,
+                program_id,
+            );
+            assert_keys_equal(*withdraw_data.key,account_address )?;
+            let (account_address, bump_seed) = get_master_address_and_bump_seed(
+                source_account_info.key,
+                program_id,
+            );
+            assert_keys_equal(*pda.key,account_address )?;
+            let pda_associated_token = get_associated_token_address(&account_address,&escrow.token_mint);
+            assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
+            let renewed = Self::renew_token_stream(program_id, &mut escrow, pda_associated_info, withdraw_data, now)?;
+            let mut amount = escrow.withdrawable_amount(now)?;
+            if escrow.rate != 0 {
+                let withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+                amount = Self::rate_stream_cap(amount, get_token_balance(pda_associated_info)?, withdraw_state.amount);
+            }
+            if amount == 0 {
+                msg!("Batch entry {}: nothing to withdraw", i);
+                // The new periods are already reserved, so the rollover has to be kept with them
+                if renewed > 0 {
+                    escrow.save(pda_data)?;
+                }
+                continue;
+            }
+            let pda_signer_seeds: &[&[_]] = &[
+                &source_account_info.key.to_bytes(),
+                &[bump_seed],
+            ];
+            invoke_signed(
+                &spl_token::instruction::transfer(
+                    token_program_info.key,
+                    pda_associated_info.key,
+                    receiver_associated_info.key,
+                    pda.key,
+                    &[pda.key],
+                    amount
+                )?,
+                &[
+                    token_program_info.clone(),
+                    pda_associated_info.clone(),
+                    receiver_associated_info.clone(),
+                    pda.clone(),
+                    system_program.clone()
+                ],&[pda_signer_seeds],
+            )?;
+            total_comission = total_comission.checked_add(25*amount/10000).ok_or(TokenError::Overflow)?;
+            escrow.withdrawn = escrow.withdrawn.checked_add(amount).ok_or(TokenError::Overflow)?;
+            escrow.save(pda_data)?;
+            // Streams the sender paid for return their rent once finished, others wait for the close instruction
+            if escrow.rate == 0 && escrow.withdrawn == escrow.amount && rent_refund_address(&escrow.rent_payer, &escrow.sender) == *source_account_info.key {
+                close_program_account(pda_data, source_account_info)?;
+            }
+            let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            withdraw_state.amount = withdraw_state.amount.checked_sub(escrow.reserved_part(amount)).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        }
+        if total_comission == 0 {
+            return Ok(());
+        }
+        let fee_account_associated_token = get_associated_token_address(fee_account.key,token_mint_info.key);
         assert_keys_equal(*associated_fee_account.key, fee_account_associated_token)?;
         if associated_fee_account.data_is_empty(){
             invoke(            
@@ -1030,136 +3097,224 @@ impl Processor {
                 ]
             )?
         }
-        allowed_amt = allowed_amt.checked_sub(escrow.withdrawn).unwrap();
-        msg!("{:?}",allowed_amt);
-        let comission: u64 = 25*allowed_amt/10000; 
-        let receiver_amount:u64=allowed_amt-comission;
-        invoke_signed(
-            &spl_token::instruction::transfer(
-                token_program_info.key,
-                pda_associated_info.key,
-                associated_fee_account.key,
-                pda.key,
-                &[pda.key],
-                comission
-            )?,
-            &[
-                token_program_info.clone(),
-                pda_associated_info.clone(),
-                associated_fee_account.clone(),
-                pda.clone(),
-                system_program.clone()
-            ],&[&pda_signer_seeds],
-        )?;
-        invoke_signed(
+        invoke(
             &spl_token::instruction::transfer(
                 token_program_info.key,
-                pda_associated_info.key,
                 receiver_associated_info.key,
-                pda.key,
-                &[pda.key],
-                receiver_amount
+                associated_fee_account.key,
+                dest_account_info.key,
+                &[dest_account_info.key],
+                total_comission
             )?,
             &[
                 token_program_info.clone(),
-                pda_associated_info.clone(),
                 receiver_associated_info.clone(),
-                pda.clone(),
-                system_program.clone()
-            ],&[&pda_signer_seeds],
+                associated_fee_account.clone(),
+                dest_account_info.clone(),
+            ],
         )?;
-        let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
-            PREFIX_TOKEN,
-            source_account_info.key,
-            &escrow.token_mint,
-            program_id,
-        );
-        assert_keys_equal(*withdraw_data.key,account_address )?;
-        let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
-        withdraw_state.amount -= escrow.amount.checked_sub(escrow.withdrawn).unwrap();
-        withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
-        // We don't need to send tokens to sender wallet since tokens are already stored in master pda associated token account
-        // Sending pda rent to sender account
-        let dest_starting_lamports = source_account_info.lamports();
-        **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-            .checked_add(pda_data.lamports())
-            .ok_or(TokenError::Overflow)?;
-
-        **pda_data.lamports.borrow_mut() = 0;
-
-        escrow.amount = 0;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
         Ok(())
     }
-    /// Function to pause token streaming
-    fn process_pause_token_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+    /// Function for keepers to push the vested solana of a stream to its recipient
+    fn process_crank_sol_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let source_account_info = next_account_info(account_info_iter)?;
-        let dest_account_info = next_account_info(account_info_iter)?;
-        let pda_data = next_account_info(account_info_iter)?;
+        let keeper_info = next_account_info(account_info_iter)?; // anyone can crank
+        let source_account_info = next_account_info(account_info_iter)?; // stream initiator address
+        let _dest_account_info = next_account_info(account_info_iter)?; // stream receiver
+        let pda = next_account_info(account_info_iter)?; // locked fund
+        let pda_data = next_account_info(account_info_iter)?; // stored data 
+        let withdraw_data = next_account_info(account_info_iter)?; // withdraw data 
+        let system_program = next_account_info(account_info_iter)?; // system program id 
+
+        if !keeper_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
         if *pda_data.owner != *program_id{
             return Err(ProgramError::InvalidArgument);
         }
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
-        let mut escrow = StreamToken::try_from_slice(&pda_data.data.borrow())?;
-        let now = Clock::get()?.unix_timestamp as u64;
-        let allowed_amt = escrow.allowed_amt(now);
-        if now >= escrow.end_time {
-            return Err(TokenError::TimeEnd.into());
-        }
-        if now < escrow.start_time{
-            return Err(TokenError::StreamNotStarted.into());
+        let escrow = Stream::load(pda_data)?;
+        assert_stream_address(pda_data.key, &Pubkey::default(), &escrow.seeds, program_id)?;
+        // Tip is paid from the vault of the stream sender
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
         }
-        if !source_account_info.is_signer && !dest_account_info.is_signer{ // Both sender and receiver can pause / resume stream
-            return Err(ProgramError::MissingRequiredSignature); 
+        let (account_address, _bump_seed) = get_master_address_and_bump_seed(
+            &escrow.sender,
+            program_id,
+        );
+        assert_keys_equal(*pda.key, account_address)?;
+        let now = Clock::get()?.unix_timestamp as u64;
+        if now < escrow.last_cranked.saturating_add(escrow.crank_interval) {
+            return Err(TokenError::CrankTooSoon.into());
         }
-        if escrow.start_time >= escrow.end_time {
-            return Err(TokenError::InvalidInstruction.into());
+        let mut amount = escrow.withdrawable_amount(now)?;
+        if escrow.rate != 0 {
+            let withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            amount = Self::rate_stream_cap(amount, pda.lamports(), withdraw_state.amount);
         }
-        if *source_account_info.key != escrow.sender || *dest_account_info.key != escrow.recipient { //Sender and Recipient both can pause or resume any transaction
-            return Err(TokenError::EscrowMismatch.into());
+        if amount == 0 {
+            msg!("Nothing to withdraw");
+            return Err(ProgramError::InsufficientFunds);
         }
-        if escrow.paused ==1{
-            return Err(TokenError::AlreadyPaused.into());
+        let crank_tip = escrow.crank_tip;
+        let crank_interval = escrow.crank_interval;
+        // Recipient signature is not needed, the withdraw only pays whoever the stream pays
+        Self::process_sol_withdraw_stream(program_id, &accounts[1..], amount)?;
+        // Crank only counts once the payout went through, a finished stream is already closed.
+        // Only streams with an interval record it, legacy layouts have no room for it.
+        if crank_interval != 0 && pda_data.lamports() > 0 {
+            let mut escrow = Stream::load(pda_data)?;
+            escrow.last_cranked = now;
+            escrow.save(pda_data)?;
+        }
+        if crank_tip == 0 {
+            return Ok(());
+        }
+        // Tip comes out of the sender's free balance, never from other streams
+        let withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        if pda.lamports().saturating_sub(crank_tip) < withdraw_state.amount {
+            msg!("Sender cannot cover the keeper tip");
+            return Ok(());
         }
-        escrow.paused = 1;
-        escrow.withdraw_limit = allowed_amt;
-        escrow.paused_at = now;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        let (_account_address, bump_seed) = get_master_address_and_bump_seed(
+            source_account_info.key,
+            program_id,
+        );
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        create_transfer(
+            pda,
+            keeper_info,
+            system_program,
+            crank_tip,
+            pda_signer_seeds
+        )?;
         Ok(())
     }
-    /// Function to resume token streaming
-    fn process_resume_token_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+    /// Function for keepers to push the vested tokens of a stream to its recipient
+    fn process_crank_token_stream(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let source_account_info = next_account_info(account_info_iter)?;
-        let dest_account_info = next_account_info(account_info_iter)?;
-        let pda_data = next_account_info(account_info_iter)?;
+        let keeper_info = next_account_info(account_info_iter)?; // anyone can crank
+        let keeper_associated_info = next_account_info(account_info_iter)?; // Associated token of keeper
+        let source_account_info = next_account_info(account_info_iter)?;  // sender 
+        let _dest_account_info = next_account_info(account_info_iter)?; // recipient
+        let pda = next_account_info(account_info_iter)?; // master pda
+        let pda_data = next_account_info(account_info_iter)?; // Program pda to store data
+        let withdraw_data = next_account_info(account_info_iter)?; // Program pda to store withdraw data
+        let token_program_info = next_account_info(account_info_iter)?; // {TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA}
+        let token_mint_info = next_account_info(account_info_iter)?; // token you would like to initilaize 
+        let rent_info = next_account_info(account_info_iter)?; // rent address
+        let pda_associated_info = next_account_info(account_info_iter)?; // Associated token of pda
+        let _receiver_associated_info = next_account_info(account_info_iter)?; // Associated token of receiver
+        let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
+        let system_program = next_account_info(account_info_iter)?;
 
+        if !keeper_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
         if *pda_data.owner != *program_id{
             return Err(ProgramError::InvalidArgument);
         }
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
+        let escrow = StreamToken::load(pda_data)?;
+        assert_stream_address(pda_data.key, &escrow.token_mint, &escrow.seeds, program_id)?;
+        // Tip is paid from the vault of the stream sender
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let (account_address, _bump_seed) = get_master_address_and_bump_seed(
+            &escrow.sender,
+            program_id,
+        );
+        assert_keys_equal(*pda.key, account_address)?;
+        assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
+        let pda_associated_token = get_associated_token_address(pda.key,token_mint_info.key);
+        assert_keys_equal(pda_associated_token, *pda_associated_info.key)?;
         let now = Clock::get()?.unix_timestamp as u64;
-        let mut escrow = StreamToken::try_from_slice(&pda_data.data.borrow())?;
-        if !source_account_info.is_signer && !dest_account_info.is_signer{ // Both sender and receiver can pause / resume stream
-            return Err(ProgramError::MissingRequiredSignature); 
+        if now < escrow.last_cranked.saturating_add(escrow.crank_interval) {
+            return Err(TokenError::CrankTooSoon.into());
         }
-        if *source_account_info.key != escrow.sender || *dest_account_info.key != escrow.recipient { //Sender and Recipient both can pause or resume any transaction
-            return Err(TokenError::EscrowMismatch.into());
+        let mut amount = escrow.withdrawable_amount(now)?;
+        if escrow.rate != 0 {
+            let withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            amount = Self::rate_stream_cap(amount, get_token_balance(pda_associated_info)?, withdraw_state.amount);
         }
-        if escrow.paused ==0{
-            return Err(TokenError::AlreadyResumed.into());
+        if amount == 0 {
+            msg!("Nothing to withdraw");
+            return Err(ProgramError::InsufficientFunds);
         }
-        let time_spent = now - escrow.paused_at;
-        escrow.paused = 0;
-        escrow.start_time += time_spent;
-        escrow.end_time += time_spent;
-        escrow.paused_at = 0;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        let crank_tip = escrow.crank_tip;
+        let crank_interval = escrow.crank_interval;
+        // Keeper pays for the recipient and fee token accounts if they are missing
+        Self::token_withdraw_stream(program_id, &accounts[2..], amount, Some(keeper_info), None)?;
+        // Crank only counts once the payout went through, a finished stream is already closed.
+        // Only streams with an interval record it, legacy layouts have no room for it.
+        if crank_interval != 0 && pda_data.lamports() > 0 {
+            let mut escrow = StreamToken::load(pda_data)?;
+            escrow.last_cranked = now;
+            escrow.save(pda_data)?;
+        }
+        if crank_tip == 0 {
+            return Ok(());
+        }
+        // Tip comes out of the sender's free balance, never from other streams
+        let withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+        if get_token_balance(pda_associated_info)?.saturating_sub(crank_tip) < withdraw_state.amount {
+            msg!("Sender cannot cover the keeper tip");
+            return Ok(());
+        }
+        let keeper_associated_token = get_associated_token_address(keeper_info.key,token_mint_info.key);
+        assert_keys_equal(keeper_associated_token, *keeper_associated_info.key)?;
+        if keeper_associated_info.data_is_empty(){
+            invoke(            
+                &spl_associated_token_account::create_associated_token_account(
+                    keeper_info.key,
+                    keeper_info.key,
+                    token_mint_info.key,
+                ),&[
+                    keeper_info.clone(),
+                    keeper_associated_info.clone(),
+                    keeper_info.clone(),
+                    token_mint_info.clone(),
+                    token_program_info.clone(),
+                    rent_info.clone(),
+                    associated_token_info.clone(),
+                    system_program.clone()
+                ]
+            )?
+        }
+        let (_account_address, bump_seed) = get_master_address_and_bump_seed(
+            source_account_info.key,
+            program_id,
+        );
+        let pda_signer_seeds: &[&[_]] = &[
+            &source_account_info.key.to_bytes(),
+            &[bump_seed],
+        ];
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                token_program_info.key,
+                pda_associated_info.key,
+                keeper_associated_info.key,
+                pda.key,
+                &[pda.key],
+                crank_tip
+            )?,
+            &[
+                token_program_info.clone(),
+                pda_associated_info.clone(),
+                keeper_associated_info.clone(),
+                pda.clone(),
+                system_program.clone()
+            ],&[pda_signer_seeds],
+        )?;
         Ok(())
     }
     /// Function to deposit solana
@@ -1539,7 +3694,7 @@ impl Processor {
         Ok(())
     }
     /// Function to initialize a solana
-    pub fn process_sol_stream_multisig(program_id: &Pubkey, accounts: &[AccountInfo], data: EscrowMultisig) -> ProgramResult {
+    pub fn process_sol_stream_multisig(program_id: &Pubkey, accounts: &[AccountInfo], data: EscrowMultisig, tokenized: bool, pause_policy: u8, pause_forfeits: bool, metadata: StreamMetadata) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let source_account_info = next_account_info(account_info_iter)?;  //sender
         let dest_account_info = next_account_info(account_info_iter)?; // recipient
@@ -1549,6 +3704,9 @@ impl Processor {
 
         // Get the rent sysvar via syscall
         let rent = Rent::get()?; //
+        // Multisig streams are always linear
+        assert_valid_pause_terms(pause_policy, pause_forfeits, true)?;
+        assert_valid_metadata(&metadata)?;
         // Since we are performing system_instruction source account must be signer.
         if !source_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
@@ -1575,29 +3733,37 @@ impl Processor {
         if k == multisig_check.signers.len(){
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        let transfer_amount =  rent.minimum_balance(std::mem::size_of::<StreamMultisig>()+355);
+        let mut escrow = StreamMultisig {
+            start_time: data.start_time,
+            end_time: data.end_time,
+            paused: 1,
+            withdraw_limit: 0,
+            sender: *source_account_info.key,
+            recipient: *dest_account_info.key,
+            amount: data.amount,
+            signed_by: data.signed_by,
+            multisig_safe: multisig_check.multisig_safe,
+            can_cancel: data.can_cancel,
+            pause_policy,
+            pause_forfeits,
+            metadata,
+            rent_payer: *source_account_info.key,
+            paused_at: 0,
+            ..Default::default()
+        };
+        let space_size = escrow.account_len()?;
         create_pda_account( 
             source_account_info,
-            transfer_amount,
-            std::mem::size_of::<StreamMultisig>()+355,
+            rent.minimum_balance(space_size),
+            space_size,
             program_id,
             system_program,
             pda_data
         )?;
-        let mut escrow = StreamMultisig::from_account(pda_data)?;
-        escrow.start_time = data.start_time;
-        escrow.end_time = data.end_time;
-        escrow.paused = 1;
-        escrow.withdraw_limit = 0;
-        escrow.sender = *source_account_info.key;
-        escrow.recipient = *dest_account_info.key;
-        escrow.amount = data.amount;
-        escrow.signed_by = data.signed_by;
-        escrow.multisig_safe = multisig_check.multisig_safe;
-        escrow.can_cancel = data.can_cancel;
-        escrow.paused_at = 0;
-        msg!("{:?}",escrow);
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        if tokenized {
+            escrow.receivable_mint = create_receivable_mint(program_id, pda_data.key, source_account_info, dest_account_info, system_program, account_info_iter)?;
+        }
+        escrow.init(pda_data)?;
         multisig_check.serialize(&mut *pda_data_multisig.data.borrow_mut())?;
         msg!("{}",pda_data.data_len());
         Ok(())
@@ -1627,7 +3793,7 @@ impl Processor {
             return Err(ProgramError::MissingRequiredSignature); 
         }
         let mut n = 0; 
-        let mut escrow = StreamMultisig::from_account(pda_data)?;
+        let mut escrow = StreamMultisig::load(pda_data)?;
         let now = Clock::get()?.unix_timestamp as u64;
         if now > escrow.start_time {
             return Err(TokenError::TimeEnd.into());
@@ -1681,9 +3847,8 @@ impl Processor {
             withdraw_state.amount += escrow.amount;
             withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
         }
-        msg!("{:?}",escrow);
         multisig_check.serialize(&mut *pda_data_multisig.data.borrow_mut())?;
-        escrow.serialize(&mut *pda_data.data.borrow_mut())?;
+        escrow.save(pda_data)?;
         Ok(())
     }
     fn process_reject_sol_stream_multisig(accounts: &[AccountInfo]) -> ProgramResult {
@@ -1692,7 +3857,7 @@ impl Processor {
         let pda_data = next_account_info(account_info_iter)?; // stored data 
         let pda_data_multisig = next_account_info(account_info_iter)?; // pda multisig data storage
 
-        let escrow = StreamMultisig::from_account(pda_data)?;
+        let escrow = StreamMultisig::load(pda_data)?;
         let multisig_check = Multisig::from_account(pda_data_multisig)?;
         let mut k = 0; 
         for i in 0..multisig_check.signers.len(){
@@ -1710,7 +3875,7 @@ impl Processor {
         if k == multisig_check.signers.len(){
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
         multisig_check.serialize(&mut &mut pda_data_multisig.data.borrow_mut()[..])?;
         let dest_starting_lamports = source_account_info.lamports();
             **source_account_info.lamports.borrow_mut() = dest_starting_lamports
@@ -2111,10 +4276,7 @@ impl Processor {
             return Err(TokenError::StreamNotStarted.into());
         }
         // Recipient can only withdraw the money that is already streamed. 
-        let mut allowed_amt = escrow.allowed_amt(now);
-        if now >= escrow.end_time {
-            allowed_amt = escrow.amount;
-        }
+        let allowed_amt = escrow.withdrawable_amount(now)?;
         msg!("You can withdraw {}",allowed_amt);
         if !dest_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
@@ -2126,10 +4288,6 @@ impl Processor {
         if amount>allowed_amt {
             return Err(ProgramError::InsufficientFunds);
         }
-        // Checking if paused stream is greater than withdraw limit
-        if escrow.paused == 1 && amount > escrow.withdraw_limit {
-            return Err(ProgramError::InsufficientFunds);
-        }
         let (_account_address_multisig, bump_seed_multisig) = get_multisig_data_and_bump_seed(
             PREFIXMULTISIGSAFE,
             multisig_pda_data.key,
@@ -2204,7 +4362,7 @@ impl Processor {
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
-        let mut escrow = StreamMultisig::from_account(pda_data)?;
+        let mut escrow = StreamMultisig::load(pda_data)?;
         if escrow.multisig_safe != *pda.key{
             return Err(TokenError::EscrowMismatch.into());
         }
@@ -2213,26 +4371,16 @@ impl Processor {
             return Err(TokenError::StreamNotStarted.into());
         }
         // Recipient can only withdraw the money that is already streamed. 
-        let mut allowed_amt = escrow.allowed_amt(now);
-        if now >= escrow.end_time {
-            allowed_amt = escrow.amount;
-        }
-        allowed_amt -=  escrow.withdrawn;
+        let allowed_amt = escrow.withdrawable_amount(now)?;
         msg!("You can withdraw {}",allowed_amt);
         if !dest_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        if *dest_account_info.key != escrow.recipient {
-            return Err(TokenError::EscrowMismatch.into());
-        }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
         // Checking if amount is greater than allowed amount
         if amount>allowed_amt {
             return Err(ProgramError::InsufficientFunds);
         }
-        // Checking if paused stream is greater than withdraw limit
-        if escrow.paused == 1 && amount > escrow.withdraw_limit {
-            return Err(ProgramError::InsufficientFunds);
-        }
         let (_account_address_multisig, bump_seed_multisig) = get_multisig_data_and_bump_seed(
             PREFIXMULTISIGSAFE,
             multisig_pda_data.key,
@@ -2259,20 +4407,14 @@ impl Processor {
             receiver_amount,
             pda_signer_seeds
         )?;
-        if escrow.paused == 1{
-            msg!("{}{}",escrow.withdraw_limit,amount);
-            escrow.withdraw_limit -= amount;
-        }
         escrow.withdrawn  = escrow.withdrawn.checked_add(amount).unwrap();
-        // Closing account to send rent to sender
-        if escrow.withdrawn == escrow.amount { 
-            let dest_starting_lamports = source_account_info.lamports();
-            **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(pda_data.lamports())
-                .ok_or(TokenError::Overflow)?;
-            **pda_data.lamports.borrow_mut() = 0;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it
+        if escrow.withdrawn == escrow.amount {
+            if let Some(rent_payer_info) = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)? {
+                close_program_account(pda_data, rent_payer_info)?;
+            }
         }
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
         let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
         withdraw_state.amount = withdraw_state.amount.checked_sub(amount).unwrap();
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
@@ -2303,10 +4445,11 @@ impl Processor {
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
-        let mut escrow = StreamMultisig::from_account(pda_data)?;
+        let mut escrow = StreamMultisig::load(pda_data)?;
         if escrow.can_cancel == false {
             return Err(TokenError::CancelNotAllowed.into());
         }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
         let multisig_check = Multisig::from_account(multisig_pda_data)?;
         let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
             PREFIXMULTISIG,
@@ -2326,14 +4469,11 @@ impl Processor {
         let now = Clock::get()?.unix_timestamp as u64;
         msg!("Current time: {}", now);
         // Amount that recipient should receive.  
-        let mut allowed_amt = escrow.allowed_amt(now);
+        let (allowed_amt, _) = escrow.cancel_split(now)?;
         if now >= escrow.end_time {
             msg!("Stream already completed");
             return Err(TokenError::StreamNotStarted.into());
         }
-        if now < escrow.start_time {
-            allowed_amt = 0;
-        }
         if *source_account_info.key != escrow.sender {
             return Err(TokenError::OwnerMismatch.into());
         }
@@ -2352,7 +4492,6 @@ impl Processor {
             &multisig_pda_data.key.to_bytes(),
             &[bump_seed_multisig],
         ];
-        allowed_amt = allowed_amt.checked_sub(escrow.withdrawn).unwrap();
         let comission: u64 = 25*allowed_amt/10000; 
         let receiver_amount:u64=allowed_amt-comission;
         create_transfer(
@@ -2374,15 +4513,12 @@ impl Processor {
         withdraw_state.amount -= escrow.amount.checked_sub(escrow.withdrawn).unwrap();
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
         // We don't need to send remaining funds to sender, its already in sender master pda account which he can withdraw with withdraw function
-        // Closing account to send rent to sender
-        let dest_starting_lamports = source_account_info.lamports();
-        **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-            .checked_add(pda_data.lamports())
-            .ok_or(TokenError::Overflow)?;
-
-        **pda_data.lamports.borrow_mut() = 0;
         escrow.amount = 0;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it
+        let rent_payer_info = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)?
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        close_program_account(pda_data, rent_payer_info)?;
         Ok(())
     }
     //Function to pause solana stream
@@ -2403,34 +4539,88 @@ impl Processor {
         if k == multisig_check.signers.len(){
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        let mut escrow = StreamMultisig::from_account(pda_data)?;
+        let mut escrow = StreamMultisig::load(pda_data)?;
         if multisig_check.multisig_safe != escrow.multisig_safe{
             return Err(TokenError::OwnerMismatch.into());
         }
         let now = Clock::get()?.unix_timestamp as u64;
-        let allowed_amt = escrow.allowed_amt(now);
         if now >= escrow.end_time {
             return Err(TokenError::TimeEnd.into());
         }
         if now < escrow.start_time{
             return Err(TokenError::StreamNotStarted.into());
         }
-        // Both sender and receiver can pause / resume stream
-        if !source_account_info.is_signer && !dest_account_info.is_signer{ 
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+
+        escrow.pause(now)?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function to edit the metadata of a solana multisig stream before it starts
+    fn process_update_sol_multisig_metadata(program_id: &Pubkey,accounts: &[AccountInfo],metadata: StreamMetadata) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !source_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
         }
+        if !StreamMultisig::is_kind(pda_data) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let mut escrow = StreamMultisig::load(pda_data)?;
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        if now >= escrow.start_time {
+            return Err(TokenError::MetadataLocked.into());
+        }
+        assert_valid_metadata(&metadata)?;
+        escrow.metadata = metadata;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    //Function to close a finished solana multisig stream and return its rent
+    fn process_close_sol_multisig_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?; // sender or recipient
+        let rent_payer_info = next_account_info(account_info_iter)?; // account that paid the stream rent
+        let pda_data = next_account_info(account_info_iter)?; // stored data
 
-        if escrow.paused ==1{
-            return Err(TokenError::AlreadyPaused.into());
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
         }
-        escrow.paused = 1;
-        escrow.withdraw_limit = allowed_amt;
-        escrow.paused_at = now;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !authority_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
+        }
+        if !StreamMultisig::is_kind(pda_data) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let escrow = StreamMultisig::load(pda_data)?;
+        if *authority_info.key != escrow.sender {
+            assert_stream_payee(authority_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        }
+        let rent_payer = rent_refund_address(&escrow.rent_payer, &escrow.sender);
+        assert_keys_equal(rent_payer, *rent_payer_info.key)?;
+        if escrow.withdrawn < escrow.amount {
+            return Err(TokenError::StreamNotFinished.into());
+        }
+        close_program_account(pda_data, rent_payer_info)?;
         Ok(())
     }
     //Function to resume solana stream
-    fn process_resume_sol_stream_multisig(accounts: &[AccountInfo]) -> ProgramResult {
+    fn process_resume_sol_stream_multisig(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let source_account_info = next_account_info(account_info_iter)?;
         let dest_account_info = next_account_info(account_info_iter)?;
@@ -2448,27 +4638,32 @@ impl Processor {
             return Err(ProgramError::MissingRequiredSignature); 
         }
         let now = Clock::get()?.unix_timestamp as u64;
-        let mut escrow = StreamMultisig::from_account(pda_data)?;
-        // Both sender and receiver can pause / resume stream
-        if !source_account_info.is_signer && !dest_account_info.is_signer{ 
-            return Err(ProgramError::MissingRequiredSignature); 
-        }
+        let mut escrow = StreamMultisig::load(pda_data)?;
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
         if multisig_check.multisig_safe != escrow.multisig_safe{
             return Err(TokenError::OwnerMismatch.into());
         }
-        if escrow.paused ==0{
-            return Err(TokenError::AlreadyResumed.into());
+        if escrow.pause_forfeits {
+            let withdraw_data = next_account_info(account_info_iter)?; // withdraw data
+            let (account_address, _bump_seed) = get_withdraw_data_and_bump_seed(
+                PREFIXMULTISIG,
+                &escrow.multisig_safe,
+                program_id,
+            );
+            assert_keys_equal(*withdraw_data.key,account_address )?;
+            let forfeited = escrow.resume_forfeit(now)?;
+            let mut withdraw_state = Withdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            withdraw_state.amount = withdraw_state.amount.checked_sub(forfeited).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        } else {
+            escrow.resume(now)?;
         }
-        let time_spent = now - escrow.paused_at;
-        escrow.paused = 0;
-        escrow.start_time += time_spent;
-        escrow.end_time += time_spent;
-        escrow.paused_at = 0;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
         Ok(())
     }
     // Function to initialize token streaming 
-    fn process_token_multisig_stream(program_id: &Pubkey, accounts: &[AccountInfo],data: TokenEscrowMultisig) -> ProgramResult {
+    fn process_token_multisig_stream(program_id: &Pubkey, accounts: &[AccountInfo],data: TokenEscrowMultisig, tokenized: bool, pause_policy: u8, pause_forfeits: bool, metadata: StreamMetadata) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let source_account_info = next_account_info(account_info_iter)?;  // sender 
         let dest_account_info = next_account_info(account_info_iter)?; // recipient
@@ -2482,6 +4677,9 @@ impl Processor {
         if token_program_info.key != &spl_token::id() {
             return Err(ProgramError::IncorrectProgramId);
         }    
+        // Multisig streams are always linear
+        assert_valid_pause_terms(pause_policy, pause_forfeits, true)?;
+        assert_valid_metadata(&metadata)?;
         // Since we are performing system_instruction source account must be signer
         if !source_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
@@ -2507,32 +4705,39 @@ impl Processor {
         if k == multisig_check.signers.len(){
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        let transfer_amount =  rent.minimum_balance(std::mem::size_of::<TokenStreamMultisig>());
+        let mut escrow = TokenStreamMultisig {
+            start_time: data.start_time,
+            end_time: data.end_time,
+            paused: 1,
+            withdraw_limit: 0,
+            sender: *source_account_info.key,
+            recipient: *dest_account_info.key,
+            amount: data.amount,
+            token_mint: *token_mint_info.key,
+            signed_by: data.signed_by,
+            multisig_safe: multisig_check.multisig_safe,
+            can_cancel: data.can_cancel,
+            pause_policy,
+            pause_forfeits,
+            metadata,
+            rent_payer: *source_account_info.key,
+            withdrawn: 0,
+            paused_at: 0,
+            ..Default::default()
+        };
+        let space_size = escrow.account_len()?;
         create_pda_account( 
             source_account_info,
-            transfer_amount+transfer_amount+transfer_amount+transfer_amount,
-            std::mem::size_of::<TokenStreamMultisig>()+355,
+            rent.minimum_balance(space_size),
+            space_size,
             program_id,
             system_program,
             pda_data
         )?;
-
-        let mut escrow = TokenStreamMultisig::from_account(pda_data)?;
-        escrow.start_time = data.start_time;
-        escrow.end_time = data.end_time;
-        escrow.paused = 1;
-        escrow.withdraw_limit = 0;
-        escrow.sender = *source_account_info.key;
-        escrow.recipient = *dest_account_info.key;
-        escrow.amount = data.amount;
-        escrow.token_mint = *token_mint_info.key;
-        escrow.signed_by = data.signed_by;
-        escrow.multisig_safe = multisig_check.multisig_safe;
-        escrow.can_cancel = data.can_cancel;
-        escrow.withdrawn = 0;
-        escrow.paused_at = 0;
-        msg!("{:?}",escrow);
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        if tokenized {
+            escrow.receivable_mint = create_receivable_mint(program_id, pda_data.key, source_account_info, dest_account_info, system_program, account_info_iter)?;
+        }
+        escrow.init(pda_data)?;
         multisig_check.serialize(&mut &mut pda_data_multisig.data.borrow_mut()[..])?;
         Ok(())
     }
@@ -2561,7 +4766,7 @@ impl Processor {
             return Err(ProgramError::MissingRequiredSignature); 
         }
         let mut n = 0; 
-        let mut escrow = TokenStreamMultisig::from_account(pda_data)?;
+        let mut escrow = TokenStreamMultisig::load(pda_data)?;
         let now = Clock::get()?.unix_timestamp as u64;
         if now > escrow.start_time {
             return Err(TokenError::TimeEnd.into());
@@ -2619,9 +4824,8 @@ impl Processor {
             withdraw_state.amount += escrow.amount;
             withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
         }
-        msg!("{:?}",escrow);
         multisig_check.serialize(&mut *pda_data_multisig.data.borrow_mut())?;
-        escrow.serialize(&mut *pda_data.data.borrow_mut())?;
+        escrow.save(pda_data)?;
         Ok(())
     }
     fn process_reject_token_stream_multisig(accounts: &[AccountInfo]) -> ProgramResult {
@@ -2631,7 +4835,7 @@ impl Processor {
         let pda_data = next_account_info(account_info_iter)?; // stored data 
         let pda_data_multisig = next_account_info(account_info_iter)?; // pda multisig data storage
 
-        let escrow = TokenStreamMultisig::from_account(pda_data)?;
+        let escrow = TokenStreamMultisig::load(pda_data)?;
         let multisig_check = Multisig::from_account(pda_data_multisig)?;
         msg!("multisig: {} escrow:{}",multisig_check.multisig_safe,escrow.multisig_safe);
         let now = Clock::get()?.unix_timestamp as u64;
@@ -2650,7 +4854,7 @@ impl Processor {
         if k == multisig_check.signers.len(){
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
         multisig_check.serialize(&mut &mut pda_data_multisig.data.borrow_mut()[..])?;
         let dest_starting_lamports = initiator_account_info.lamports();
             **initiator_account_info.lamports.borrow_mut() = dest_starting_lamports
@@ -2702,11 +4906,7 @@ impl Processor {
             return Err(TokenError::StreamNotStarted.into());
         }
         // Recipient can only withdraw the money that is already streamed. 
-        let mut allowed_amt = escrow.allowed_amt(now);
-        if now >= escrow.end_time {
-            msg!("Stream has been successfully completed");
-            allowed_amt = escrow.amount;
-        }
+        let allowed_amt = escrow.withdrawable_amount(now)?;
         // let rent = &Rent::from_account_info(dest_account_info)?;
         msg!("{} allowed_amt",allowed_amt);
         if *dest_account_info.key != escrow.recipient {
@@ -2717,10 +4917,6 @@ impl Processor {
             return Err(ProgramError::InsufficientFunds);
         }
         msg!("{}",amount);
-        if escrow.paused == 1 && amount > escrow.withdraw_limit {
-            msg!("{} is your withdraw limit",escrow.withdraw_limit);
-            return Err(ProgramError::InsufficientFunds);
-        }
 
         let (account_address_multisig, bump_seed) = get_multisig_data_and_bump_seed(
             PREFIXMULTISIGSAFE,
@@ -2874,7 +5070,7 @@ impl Processor {
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
-        let mut escrow = TokenStreamMultisig::from_account(pda_data)?;
+        let mut escrow = TokenStreamMultisig::load(pda_data)?;
         assert_keys_equal(escrow.token_mint, *token_mint_info.key)?;
         let now = Clock::get()?.unix_timestamp as u64;
         msg!("current time: {}",now);
@@ -2883,25 +5079,15 @@ impl Processor {
             return Err(TokenError::StreamNotStarted.into());
         }
         // Recipient can only withdraw the money that is already streamed. 
-        let mut allowed_amt = escrow.allowed_amt(now);
-        if now >= escrow.end_time {
-            allowed_amt = escrow.amount;
-        }
-        allowed_amt -=  escrow.withdrawn;
+        let allowed_amt = escrow.withdrawable_amount(now)?;
         // let rent = &Rent::from_account_info(dest_account_info)?;
         msg!("{} allowed_amt",allowed_amt);
-        if *dest_account_info.key != escrow.recipient {
-            return Err(TokenError::EscrowMismatch.into());
-        }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
         if amount>allowed_amt {
             msg!("{} is not yet streamlined.",amount);
             return Err(ProgramError::InsufficientFunds);
         }
         msg!("{}",amount);
-        if escrow.paused == 1 && amount > escrow.withdraw_limit {
-            msg!("{} is your withdraw limit",escrow.withdraw_limit);
-            return Err(ProgramError::InsufficientFunds);
-        }
 
         let (account_address_multisig, bump_seed) = get_multisig_data_and_bump_seed(
             PREFIXMULTISIGSAFE,
@@ -2999,23 +5185,17 @@ impl Processor {
                 system_program.clone()
             ],&[&pda_signer_seeds],
         )?;
-        if escrow.paused == 1{
-            msg!("{}{}",escrow.withdraw_limit,amount);
-            escrow.withdraw_limit -= amount;
-        }
         msg!("amount: {}",amount);
         escrow.withdrawn = escrow.withdrawn.checked_add(amount).unwrap();
         msg!("amount: {}",escrow.withdrawn);
 
-        // Closing account to send rent to sender
-        if escrow.withdrawn == escrow.amount { 
-            let dest_starting_lamports = source_account_info.lamports();
-            **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(pda_data.lamports())
-                .ok_or(TokenError::Overflow)?;
-            **pda_data.lamports.borrow_mut() = 0;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it
+        if escrow.withdrawn == escrow.amount {
+            if let Some(rent_payer_info) = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)? {
+                close_program_account(pda_data, rent_payer_info)?;
+            }
         }
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
         let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
         withdraw_state.amount = withdraw_state.amount.checked_sub(amount).unwrap();
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
@@ -3063,22 +5243,20 @@ impl Processor {
         if !source_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
         }
-        let mut escrow = TokenStreamMultisig::from_account(pda_data)?;
+        let mut escrow = TokenStreamMultisig::load(pda_data)?;
         let now = Clock::get()?.unix_timestamp as u64;
         msg!("current time: {}",now);
 
         if escrow.can_cancel == false {
             return Err(TokenError::CancelNotAllowed.into());
         }
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
         if escrow.multisig_safe != *pda.key {
             return Err(TokenError::OwnerMismatch.into());
         }
         // Amount that recipient should receive.  
-        let mut allowed_amt = escrow.allowed_amt(now);
+        let (allowed_amt, _) = escrow.cancel_split(now)?;
         msg!("allowed_amt: {}",allowed_amt);
-        if now < escrow.start_time {
-            allowed_amt = 0;
-        }
         if now >= escrow.end_time {
             msg!("Stream already completed");
             return Err(TokenError::TimeEnd.into());
@@ -3142,7 +5320,6 @@ impl Processor {
                 ]
             )?
         }
-        allowed_amt = allowed_amt.checked_sub(escrow.withdrawn).unwrap();
         msg!("withdrawn: {}",escrow.withdrawn);
         msg!("subtracting withdrawn: {}",allowed_amt);
         let comission: u64 = 25*allowed_amt/10000; 
@@ -3194,16 +5371,12 @@ impl Processor {
         withdraw_state.amount -= escrow.amount.checked_sub(escrow.withdrawn).unwrap();
         withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
         // We don't need to send tkens to sender wallet since tokens are already stored in master pda associated token account
-        // Sending pda rent to sender account
-        let dest_starting_lamports = source_account_info.lamports();
-        **source_account_info.lamports.borrow_mut() = dest_starting_lamports
-            .checked_add(pda_data.lamports())
-            .ok_or(TokenError::Overflow)?;
-
-        **pda_data.lamports.borrow_mut() = 0;
-
         escrow.amount = 0;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
+        // Closing account to send rent back to whoever paid it
+        let rent_payer_info = Self::rent_payer_account(&escrow.rent_payer, &escrow.sender, source_account_info, account_info_iter)?
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        close_program_account(pda_data, rent_payer_info)?;
         Ok(())
     }
     /// Function to pause token streaming
@@ -3229,32 +5402,88 @@ impl Processor {
         if pda_data.data_is_empty(){
             return Err(ProgramError::UninitializedAccount);
         }
-        let mut escrow = TokenStreamMultisig::from_account(pda_data)?;
+        let mut escrow = TokenStreamMultisig::load(pda_data)?;
         if multisig_check.multisig_safe != escrow.multisig_safe{
             return Err(TokenError::OwnerMismatch.into());
         }
         let now = Clock::get()?.unix_timestamp as u64;
-        let allowed_amt = escrow.allowed_amt(now);
         if now >= escrow.end_time {
             return Err(TokenError::TimeEnd.into());
         }
         if now < escrow.start_time{
             return Err(TokenError::StreamNotStarted.into());
         }
-        if !source_account_info.is_signer && !dest_account_info.is_signer{ // Both sender and receiver can pause / resume stream
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+
+        if k == multisig_check.signers.len() {
+            return Err(TokenError::EscrowMismatch.into());
+        }
+        escrow.pause(now)?;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function to edit the metadata of a token multisig stream before it starts
+    fn process_update_token_multisig_metadata(program_id: &Pubkey,accounts: &[AccountInfo],metadata: StreamMetadata) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?; // sender
+        let pda_data = next_account_info(account_info_iter)?; // stored data
+
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !source_account_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature); 
         }
+        if !TokenStreamMultisig::is_kind(pda_data) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let mut escrow = TokenStreamMultisig::load(pda_data)?;
+        if *source_account_info.key != escrow.sender {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        let now = Clock::get()?.unix_timestamp as u64;
+        if now >= escrow.start_time {
+            return Err(TokenError::MetadataLocked.into());
+        }
+        assert_valid_metadata(&metadata)?;
+        escrow.metadata = metadata;
+        escrow.save(pda_data)?;
+        Ok(())
+    }
+    /// Function to close a finished token multisig stream and return its rent
+    fn process_close_token_multisig_stream(program_id: &Pubkey,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?; // sender or recipient
+        let rent_payer_info = next_account_info(account_info_iter)?; // account that paid the stream rent
+        let pda_data = next_account_info(account_info_iter)?; // stored data
 
-        if k == multisig_check.signers.len() || *dest_account_info.key != escrow.recipient { //Sender and Recipient both can pause or resume any transaction
-            return Err(TokenError::EscrowMismatch.into());
+        if *pda_data.owner != *program_id{
+            return Err(ProgramError::InvalidArgument);
+        }
+        if pda_data.data_is_empty(){
+            return Err(ProgramError::UninitializedAccount);
         }
-        if escrow.paused ==1{
-            return Err(TokenError::AlreadyPaused.into());
+        if !authority_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature); 
         }
-        escrow.paused = 1;
-        escrow.withdraw_limit = allowed_amt;
-        escrow.paused_at = now;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        if !TokenStreamMultisig::is_kind(pda_data) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let escrow = TokenStreamMultisig::load(pda_data)?;
+        if *authority_info.key != escrow.sender {
+            assert_stream_payee(authority_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        }
+        let rent_payer = rent_refund_address(&escrow.rent_payer, &escrow.sender);
+        assert_keys_equal(rent_payer, *rent_payer_info.key)?;
+        if escrow.withdrawn < escrow.amount {
+            return Err(TokenError::StreamNotFinished.into());
+        }
+        close_program_account(pda_data, rent_payer_info)?;
         Ok(())
     }
     /// Function to resume token streaming
@@ -3282,25 +5511,32 @@ impl Processor {
             return Err(ProgramError::UninitializedAccount);
         }
         let now = Clock::get()?.unix_timestamp as u64;
-        let mut escrow = TokenStreamMultisig::from_account(pda_data)?;
+        let mut escrow = TokenStreamMultisig::load(pda_data)?;
         if multisig_check.multisig_safe != escrow.multisig_safe{
             return Err(TokenError::OwnerMismatch.into());
         }
-        if !source_account_info.is_signer && !dest_account_info.is_signer{ // Both sender and receiver can pause / resume stream
-            return Err(ProgramError::MissingRequiredSignature); 
-        }
-        if k == multisig_check.signers.len() || *dest_account_info.key != escrow.recipient { //Sender and Recipient both can pause or resume any transaction
+        assert_pause_allowed(escrow.pause_policy, source_account_info, dest_account_info)?;
+        assert_stream_payee(dest_account_info.key, &escrow.recipient, &escrow.receivable_mint, account_info_iter)?;
+        if k == multisig_check.signers.len() {
             return Err(TokenError::EscrowMismatch.into());
         }
-        if escrow.paused ==0{
-            return Err(TokenError::AlreadyResumed.into());
+        if escrow.pause_forfeits {
+            let withdraw_data = next_account_info(account_info_iter)?; // withdraw data
+            let (account_address, _bump_seed) = get_token_withdraw_data_and_bump_seed(
+                PREFIXMULTISIG,
+                &escrow.multisig_safe,
+                &escrow.token_mint,
+                program_id,
+            );
+            assert_keys_equal(*withdraw_data.key,account_address )?;
+            let forfeited = escrow.resume_forfeit(now)?;
+            let mut withdraw_state = TokenWithdraw::try_from_slice(&withdraw_data.data.borrow())?;
+            withdraw_state.amount = withdraw_state.amount.checked_sub(forfeited).ok_or(TokenError::Overflow)?;
+            withdraw_state.serialize(&mut &mut withdraw_data.data.borrow_mut()[..])?;
+        } else {
+            escrow.resume(now)?;
         }
-        let time_spent = now - escrow.paused_at;
-        escrow.paused = 0;
-        escrow.start_time += time_spent;
-        escrow.end_time += time_spent;
-        escrow.paused_at = 0;
-        escrow.serialize(&mut &mut pda_data.data.borrow_mut()[..])?;
+        escrow.save(pda_data)?;
         Ok(())
     }
     /// Function to deposit solana
@@ -3515,7 +5751,7 @@ impl Processor {
             }) => {
                 msg!("Instruction: Sol Withdraw");
                 let pda_data = &accounts[3];// Program pda to store data
-                if pda_data.data_len() != 120 {
+                if !Stream::is_kind(pda_data) {
                     Self::process_sol_withdraw_stream_deprecated(program_id,accounts,amount)
                 }
                 else{
@@ -3548,7 +5784,7 @@ impl Processor {
                 msg!("Instruction: Token Withdraw");
                 let pda_data = &accounts[3];// Program pda to store data
                 msg!("{}",pda_data.data_len());
-                if pda_data.data_len() != 152 {
+                if !StreamToken::is_kind(pda_data) {
                     Self::process_token_withdraw_stream_deprecated(program_id,accounts,amount)
                 }
                 else{
@@ -3625,15 +5861,15 @@ impl Processor {
                 msg!("Instruction: Signing multisig");
                 Self::process_sign_stream(program_id,accounts) 
             }
-            TokenInstruction::ProcessSolMultiSigStream{whitelist_v3} => {
+            TokenInstruction::ProcessSolMultiSigStream{whitelist_v3,tokenized,pause_policy,pause_forfeits,metadata} => {
                 msg!("Instruction: Streaming MultiSig");
-                Self::process_sol_stream_multisig(program_id,accounts,whitelist_v3) 
+                Self::process_sol_stream_multisig(program_id,accounts,whitelist_v3,tokenized,pause_policy,pause_forfeits,metadata) 
             }
             TokenInstruction::ProcessSolWithdrawStreamMultisig (ProcessSolWithdrawStreamMultisig{
                 amount}) =>{
                     let pda_data = &accounts[3];// Program pda to store data
                     msg!("pda_data: {}",pda_data.data_len());
-                    if pda_data.data_len() != 539 {
+                    if !StreamMultisig::is_kind(pda_data) {
                         Self::process_sol_withdraw_stream_multisig_deprecated(program_id,accounts,amount)
                     }
                     else {
@@ -3650,22 +5886,22 @@ impl Processor {
             }
             TokenInstruction::ProcessResumeMultisigStream=> {
                 msg!("Instruction: Stream Resume ");
-                Self::process_resume_sol_stream_multisig(accounts)
+                Self::process_resume_sol_stream_multisig(program_id,accounts)
             }
             TokenInstruction::ProcessRejectMultisigStream=> {
                 msg!("Instruction: Rejecting stream ");
                 Self::process_reject_sol_stream_multisig(accounts)
             }
-            TokenInstruction::ProcessSolTokenMultiSigStream{whitelist_v4}=>{
+            TokenInstruction::ProcessSolTokenMultiSigStream{whitelist_v4,tokenized,pause_policy,pause_forfeits,metadata}=>{
                 msg!("Instruction: Streaming Token MultiSig");
-                Self::process_token_multisig_stream(program_id,accounts,whitelist_v4) 
+                Self::process_token_multisig_stream(program_id,accounts,whitelist_v4,tokenized,pause_policy,pause_forfeits,metadata) 
             }
             TokenInstruction::ProcessTokenWithdrawStreamMultisig (ProcessTokenWithdrawStreamMultisig{
                 amount}) =>{
                     msg!("Instruction: Withdraw Token MultiSig");
                     let pda_data = &accounts[4];// Program pda to store data
                     msg!("pda_data: {}",pda_data.data_len());
-                    if pda_data.data_len() != 571 {
+                    if !TokenStreamMultisig::is_kind(pda_data) {
                         Self::process_token_withdraw_multisig_stream_deprecated(program_id,accounts,amount)
                     }
                     else {
@@ -3716,6 +5952,142 @@ impl Processor {
                 msg!("Instruction: Rejecting token transfer multisig");
                 Self::process_transfer_token_reject_multisig(program_id,accounts) 
             }
+            TokenInstruction::ProcessSolVestingStream{data} => {
+                msg!("Instruction: Sol Vesting Stream");
+                Self::process_sol_vesting_stream(program_id,accounts,data)
+            }
+            TokenInstruction::ProcessTokenVestingStream{data} => {
+                msg!("Instruction: Token Vesting Stream");
+                Self::process_token_vesting_stream(program_id,accounts,data)
+            }
+            TokenInstruction::ProcessUpdateSolRecipient => {
+                msg!("Instruction: Update Sol Stream Recipient");
+                Self::process_update_sol_recipient(program_id,accounts)
+            }
+            TokenInstruction::ProcessUpdateTokenRecipient => {
+                msg!("Instruction: Update Token Stream Recipient");
+                Self::process_update_token_recipient(program_id,accounts)
+            }
+            TokenInstruction::ProcessUpdateSolSender => {
+                msg!("Instruction: Update Sol Stream Sender");
+                Self::process_update_sol_sender(program_id,accounts)
+            }
+            TokenInstruction::ProcessUpdateTokenSender => {
+                msg!("Instruction: Update Token Stream Sender");
+                Self::process_update_token_sender(program_id,accounts)
+            }
+            TokenInstruction::ProcessCrankSolStream => {
+                msg!("Instruction: Crank Sol Stream");
+                Self::process_crank_sol_stream(program_id,accounts)
+            }
+            TokenInstruction::ProcessCrankTokenStream => {
+                msg!("Instruction: Crank Token Stream");
+                Self::process_crank_token_stream(program_id,accounts)
+            }
+            TokenInstruction::ProcessSolBatchStream{streams} => {
+                msg!("Instruction: Sol Batch Stream");
+                Self::process_sol_batch_stream(program_id,accounts,streams)
+            }
+            TokenInstruction::ProcessTokenBatchStream{streams} => {
+                msg!("Instruction: Token Batch Stream");
+                Self::process_token_batch_stream(program_id,accounts,streams)
+            }
+            TokenInstruction::ProcessSolBatchWithdraw => {
+                msg!("Instruction: Sol Batch Withdraw");
+                Self::process_sol_batch_withdraw(program_id,accounts)
+            }
+            TokenInstruction::ProcessTokenBatchWithdraw => {
+                msg!("Instruction: Token Batch Withdraw");
+                Self::process_token_batch_withdraw(program_id,accounts)
+            }
+            TokenInstruction::ProcessTopUpSolStream{amount,extend_end} => {
+                msg!("Instruction: Top Up Sol Stream");
+                Self::process_top_up_sol_stream(program_id,accounts,amount,extend_end)
+            }
+            TokenInstruction::ProcessTopUpTokenStream{amount,extend_end} => {
+                msg!("Instruction: Top Up Token Stream");
+                Self::process_top_up_token_stream(program_id,accounts,amount,extend_end)
+            }
+            TokenInstruction::ProcessScheduleSolPause{pause_start,pause_end} => {
+                msg!("Instruction: Schedule Sol Stream Pause");
+                Self::process_schedule_sol_pause(program_id,accounts,pause_start,pause_end)
+            }
+            TokenInstruction::ProcessScheduleTokenPause{pause_start,pause_end} => {
+                msg!("Instruction: Schedule Token Stream Pause");
+                Self::process_schedule_token_pause(program_id,accounts,pause_start,pause_end)
+            }
+            TokenInstruction::ProcessReduceSolStream{amount} => {
+                msg!("Instruction: Reduce Sol Stream");
+                Self::process_reduce_sol_stream(program_id,accounts,amount)
+            }
+            TokenInstruction::ProcessReduceTokenStream{amount} => {
+                msg!("Instruction: Reduce Token Stream");
+                Self::process_reduce_token_stream(program_id,accounts,amount)
+            }
+            TokenInstruction::ProcessUpdateSolMetadata{metadata} => {
+                msg!("Instruction: Update Sol Stream Metadata");
+                Self::process_update_sol_metadata(program_id,accounts,metadata)
+            }
+            TokenInstruction::ProcessUpdateTokenMetadata{metadata} => {
+                msg!("Instruction: Update Token Stream Metadata");
+                Self::process_update_token_metadata(program_id,accounts,metadata)
+            }
+            TokenInstruction::ProcessUpdateSolMultisigMetadata{metadata} => {
+                msg!("Instruction: Update Sol Multisig Stream Metadata");
+                Self::process_update_sol_multisig_metadata(program_id,accounts,metadata)
+            }
+            TokenInstruction::ProcessUpdateTokenMultisigMetadata{metadata} => {
+                msg!("Instruction: Update Token Multisig Stream Metadata");
+                Self::process_update_token_multisig_metadata(program_id,accounts,metadata)
+            }
+            TokenInstruction::ProcessCloseSolStream => {
+                msg!("Instruction: Close Sol Stream");
+                Self::process_close_sol_stream(program_id,accounts)
+            }
+            TokenInstruction::ProcessCloseTokenStream => {
+                msg!("Instruction: Close Token Stream");
+                Self::process_close_token_stream(program_id,accounts)
+            }
+            TokenInstruction::ProcessCloseSolMultisigStream => {
+                msg!("Instruction: Close Sol Multisig Stream");
+                Self::process_close_sol_multisig_stream(program_id,accounts)
+            }
+            TokenInstruction::ProcessCloseTokenMultisigStream => {
+                msg!("Instruction: Close Token Multisig Stream");
+                Self::process_close_token_multisig_stream(program_id,accounts)
+            }
+            TokenInstruction::ProcessSolBeneficiaryWithdraw => {
+                msg!("Instruction: Beneficiary Withdraw Sol Stream");
+                Self::process_sol_beneficiary_withdraw(program_id,accounts)
+            }
+            TokenInstruction::ProcessTokenBeneficiaryWithdraw => {
+                msg!("Instruction: Beneficiary Withdraw Token Stream");
+                Self::process_token_beneficiary_withdraw(program_id,accounts)
+            }
+            TokenInstruction::ProcessSolWithdrawStreamTo{amount} => {
+                msg!("Instruction: Sol Withdraw To Destination");
+                Self::process_sol_withdraw_stream_to(program_id,accounts,amount)
+            }
+            TokenInstruction::ProcessTokenWithdrawStreamTo{amount} => {
+                msg!("Instruction: Token Withdraw To Destination");
+                Self::process_token_withdraw_stream_to(program_id,accounts,amount)
+            }
+            TokenInstruction::ProcessFreezeSolStream => {
+                msg!("Instruction: Freeze Sol Stream");
+                Self::process_freeze_sol_stream(program_id,accounts)
+            }
+            TokenInstruction::ProcessFreezeTokenStream => {
+                msg!("Instruction: Freeze Token Stream");
+                Self::process_freeze_token_stream(program_id,accounts)
+            }
+            TokenInstruction::ProcessResolveSolStream{release_to_recipient} => {
+                msg!("Instruction: Resolve Sol Stream");
+                Self::process_resolve_sol_stream(program_id,accounts,release_to_recipient)
+            }
+            TokenInstruction::ProcessResolveTokenStream{release_to_recipient} => {
+                msg!("Instruction: Resolve Token Stream");
+                Self::process_resolve_token_stream(program_id,accounts,release_to_recipient)
+            }
         }
     }
 }
@@ -3739,7 +6111,1015 @@ impl PrintProgramError for TokenError {
             TokenError::StreamAlreadyCreated=>msg!("Stream Already Created"),
             TokenError::StreamNotStarted=>msg!("Stream has not been started"),
             TokenError::StreamedAmt=>msg!("Cannot withdraw streaming amount"),
-            TokenError::CancelNotAllowed=>msg!("cannot cancel this transaction")
+            TokenError::CancelNotAllowed=>msg!("cannot cancel this transaction"),
+            TokenError::CliffNotReached=>msg!("Stream cliff has not been reached"),
+            TokenError::CrankTooSoon=>msg!("Stream was cranked too recently"),
+            TokenError::PauseNotAllowed=>msg!("Stream cannot be paused"),
+            TokenError::MetadataLocked=>msg!("Stream metadata can no longer be changed"),
+            TokenError::StreamNotFinished=>msg!("Stream has not finished yet"),
+            TokenError::StreamFrozen=>msg!("Stream is frozen by its arbiter"),
+            TokenError::LegacyLayout=>msg!("Stream account uses the legacy layout"),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{state::{stream_header, Beneficiary}, utils::get_stream_address_and_bump_seed, STREAM_MAGIC, STREAM_VERSION};
+    use solana_program::{entrypoint::SUCCESS, instruction::Instruction, program_pack::Pack, program_stubs, system_instruction};
+    use std::cell::RefCell;
+
+    const NOW: u64 = 1_000_000;
+
+    thread_local! {
+        // Instructions invoked by the handlers of the running test
+        static INVOKED: RefCell<Vec<Instruction>> = const { RefCell::new(vec![]) };
+    }
+
+    // Clock syscall answering NOW and invokes recorded in INVOKED instead of run,
+    // the other syscalls keep their default stubs
+    struct ClockStubs;
+    impl program_stubs::SyscallStubs for ClockStubs {
+        fn sol_invoke_signed(&self, instruction: &Instruction, _account_infos: &[AccountInfo], _signers_seeds: &[&[&[u8]]]) -> ProgramResult {
+            INVOKED.with(|invoked| invoked.borrow_mut().push(instruction.clone()));
+            Ok(())
+        }
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock { unix_timestamp: NOW as i64, ..Clock::default() };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            SUCCESS
+        }
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+    }
+
+    struct TestAccount {
+        key: Pubkey,
+        is_signer: bool,
+        lamports: u64,
+        data: Vec<u8>,
+        owner: Pubkey,
+    }
+    impl TestAccount {
+        fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+            TestAccount { key, is_signer: false, lamports: 1_000_000, data, owner }
+        }
+        fn signer(key: Pubkey) -> Self {
+            TestAccount { is_signer: true, ..Self::new(key, system_program::id(), vec![]) }
+        }
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(&self.key, self.is_signer, true, &mut self.lamports, &mut self.data, &self.owner, false, 0)
+        }
+    }
+
+    fn invoked() -> Vec<Instruction> {
+        INVOKED.with(|invoked| invoked.borrow().clone())
+    }
+
+    fn stream_data<T: StreamAccount>(stream: &T) -> Vec<u8> {
+        let mut data = STREAM_MAGIC.to_vec();
+        data.extend_from_slice(&[STREAM_VERSION, T::KIND]);
+        data.extend(stream.try_to_vec().unwrap());
+        data
+    }
+
+    // Half vested stream of 1000 lamports, with everything reserved in the sender's withdraw data
+    fn running_stream() -> Stream {
+        Stream {
+            start_time: NOW - 50,
+            end_time: NOW + 50,
+            amount: 1000,
+            sender: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            periods: 1,
+            ..Default::default()
+        }
+    }
+
+    // source, dest, pda, pda_data, withdraw_data, system_program and fee accounts of a solana withdraw
+    fn withdraw_accounts(program_id: &Pubkey, stream: &Stream, source: Pubkey) -> Vec<TestAccount> {
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let (pda, _) = get_master_address_and_bump_seed(&source, program_id);
+        let (withdraw_data, _) = get_withdraw_data_and_bump_seed(PREFIX, &source, program_id);
+        let fee_receiver = Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        vec![
+            TestAccount::signer(source),
+            TestAccount::signer(stream.recipient),
+            TestAccount::new(pda, system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), *program_id, stream_data(stream)),
+            TestAccount::new(withdraw_data, *program_id, Withdraw { amount: stream.amount }.try_to_vec().unwrap()),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+            TestAccount::new(fee_receiver, system_program::id(), vec![]),
+        ]
+    }
+
+    #[test]
+    fn withdraw_and_cancel_need_withdraw_data_owned_by_the_program() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[4].owner = Pubkey::new_unique();
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_sol_withdraw_stream(&program_id, &infos, 100), Err(ProgramError::InvalidArgument));
+        assert_eq!(Processor::process_cancel_sol_stream(&program_id, &infos), Err(ProgramError::InvalidArgument));
+
+        let token_stream = StreamToken {
+            start_time: stream.start_time,
+            end_time: stream.end_time,
+            amount: stream.amount,
+            sender: stream.sender,
+            recipient: stream.recipient,
+            token_mint: Pubkey::new_unique(),
+            periods: 1,
+            ..Default::default()
+        };
+        let mut accounts = token_crank_accounts(&program_id, &token_stream, Pubkey::new_unique());
+        accounts[6].owner = Pubkey::new_unique();
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_token_withdraw_stream(&program_id, &infos[2..], 100), Err(ProgramError::InvalidArgument));
+        assert_eq!(Processor::process_token_cancel_stream(&program_id, &infos[2..]), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn withdraw_pays_out_only_what_has_vested() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_withdraw_stream(&program_id, &infos, 501),
+            Err(ProgramError::InsufficientFunds)
+        );
+        Processor::process_sol_withdraw_stream(&program_id, &infos, 500).unwrap();
+        assert_eq!(Stream::load(&infos[3]).unwrap().withdrawn, 500);
+        assert_eq!(Withdraw::try_from_slice(&infos[4].data.borrow()).unwrap().amount, 500);
+    }
+
+    #[test]
+    fn withdraw_rejects_a_source_that_is_not_the_sender() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = withdraw_accounts(&program_id, &stream, Pubkey::new_unique());
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_withdraw_stream(&program_id, &infos, 100),
+            Err(TokenError::OwnerMismatch.into())
+        );
+    }
+
+    #[test]
+    fn withdraw_rejects_a_vault_of_another_sender() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[2].key = Pubkey::new_unique();
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_withdraw_stream(&program_id, &infos, 100),
+            Err(TokenError::PublicKeyMismatch.into())
+        );
+    }
+
+    #[test]
+    fn withdraw_rejects_a_payee_that_is_not_the_recipient() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[1].key = Pubkey::new_unique();
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_withdraw_stream(&program_id, &infos, 100),
+            Err(TokenError::EscrowMismatch.into())
+        );
+    }
+
+    #[test]
+    fn withdraw_rejects_a_frozen_stream() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.frozen = true;
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_withdraw_stream(&program_id, &infos, 100),
+            Err(TokenError::StreamFrozen.into())
+        );
+    }
+
+    #[test]
+    fn crank_records_the_crank_after_paying_out() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.crank_interval = 10;
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[1].is_signer = false;
+        accounts.insert(0, TestAccount::signer(Pubkey::new_unique()));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_crank_sol_stream(&program_id, &infos).unwrap();
+        let escrow = Stream::load(&infos[4]).unwrap();
+        assert_eq!((escrow.withdrawn, escrow.last_cranked), (500, NOW));
+        assert_eq!(
+            Processor::process_crank_sol_stream(&program_id, &infos),
+            Err(TokenError::CrankTooSoon.into())
+        );
+    }
+
+    // keeper, keeper token, source, dest, pda, pda_data, withdraw_data, token program, mint, rent, pda token,
+    // receiver token, associated token program, system program, fee and fee token accounts of a token crank
+    fn token_crank_accounts(program_id: &Pubkey, stream: &StreamToken, receiver_associated: Pubkey) -> Vec<TestAccount> {
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let keeper = Pubkey::new_unique();
+        let (pda, _) = get_master_address_and_bump_seed(&stream.sender, program_id);
+        let (withdraw_data, _) = get_token_withdraw_data_and_bump_seed(PREFIX_TOKEN, &stream.sender, &stream.token_mint, program_id);
+        let fee_receiver = Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        vec![
+            TestAccount::signer(keeper),
+            TestAccount::new(get_associated_token_address(&keeper, &stream.token_mint), spl_token::id(), vec![]),
+            TestAccount::new(stream.sender, system_program::id(), vec![]),
+            TestAccount::new(stream.recipient, system_program::id(), vec![]),
+            TestAccount::new(pda, system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), *program_id, stream_data(stream)),
+            TestAccount::new(withdraw_data, *program_id, TokenWithdraw { amount: stream.amount }.try_to_vec().unwrap()),
+            TestAccount::new(spl_token::id(), Pubkey::default(), vec![]),
+            TestAccount::new(stream.token_mint, spl_token::id(), vec![]),
+            TestAccount::new(solana_program::sysvar::rent::id(), Pubkey::default(), vec![]),
+            TestAccount::new(get_associated_token_address(&pda, &stream.token_mint), spl_token::id(), vec![]),
+            TestAccount::new(receiver_associated, spl_token::id(), vec![]),
+            TestAccount::new(spl_associated_token_account::id(), Pubkey::default(), vec![]),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+            TestAccount::new(fee_receiver, system_program::id(), vec![]),
+            TestAccount::new(get_associated_token_address(&fee_receiver, &stream.token_mint), spl_token::id(), vec![]),
+        ]
+    }
+
+    #[test]
+    fn token_crank_only_pays_the_recipient_token_account() {
+        let program_id = Pubkey::new_unique();
+        let sol_stream = running_stream();
+        let stream = StreamToken {
+            start_time: sol_stream.start_time,
+            end_time: sol_stream.end_time,
+            amount: sol_stream.amount,
+            sender: sol_stream.sender,
+            recipient: sol_stream.recipient,
+            token_mint: Pubkey::new_unique(),
+            periods: 1,
+            crank_interval: 10,
+            ..Default::default()
+        };
+        // Keeper passing its own token account of the stream mint
+        let keeper_token = Pubkey::new_unique();
+        let mut accounts = token_crank_accounts(&program_id, &stream, keeper_token);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_crank_token_stream(&program_id, &infos),
+            Err(TokenError::PublicKeyMismatch.into())
+        );
+        assert_eq!(StreamToken::load(&infos[5]).unwrap().withdrawn, 0);
+
+        let receiver_token = get_associated_token_address(&stream.recipient, &stream.token_mint);
+        let mut accounts = token_crank_accounts(&program_id, &stream, receiver_token);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_crank_token_stream(&program_id, &infos).unwrap();
+        let escrow = StreamToken::load(&infos[5]).unwrap();
+        assert_eq!((escrow.withdrawn, escrow.last_cranked), (500, NOW));
+    }
+
+    #[test]
+    fn crank_pays_out_a_legacy_stream() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[3].data = stream.to_legacy().try_to_vec().unwrap();
+        accounts[1].is_signer = false;
+        accounts.insert(0, TestAccount::signer(Pubkey::new_unique()));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_crank_sol_stream(&program_id, &infos).unwrap();
+        assert_eq!(stream_header(&infos[4].data.borrow()), None);
+        assert_eq!(Stream::load(&infos[4]).unwrap().withdrawn, 500);
+    }
+
+    #[test]
+    fn crank_rejects_a_source_that_is_not_the_sender() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = withdraw_accounts(&program_id, &stream, Pubkey::new_unique());
+        accounts.insert(0, TestAccount::signer(Pubkey::new_unique()));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_crank_sol_stream(&program_id, &infos),
+            Err(TokenError::OwnerMismatch.into())
+        );
+    }
+
+    #[test]
+    fn crank_cannot_skip_a_recipient_signature() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.recipient_must_sign = true;
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[1].is_signer = false;
+        accounts.insert(0, TestAccount::signer(Pubkey::new_unique()));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_crank_sol_stream(&program_id, &infos),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(Stream::load(&infos[4]).unwrap().last_cranked, 0);
+    }
+
+    #[test]
+    fn frozen_stream_cannot_change_recipient() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.frozen = true;
+        let mut accounts = [
+            TestAccount::signer(stream.recipient),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), program_id, stream_data(&stream)),
+        ];
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_update_sol_recipient(&program_id, &infos),
+            Err(TokenError::StreamFrozen.into())
+        );
+    }
+
+    #[test]
+    fn recipient_update_keeps_the_parties_apart() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.arbiter = Pubkey::new_unique();
+        for new_recipient in [stream.sender, stream.arbiter] {
+            let mut accounts = [
+                TestAccount::signer(stream.recipient),
+                TestAccount::new(new_recipient, system_program::id(), vec![]),
+                TestAccount::new(Pubkey::new_unique(), program_id, stream_data(&stream)),
+            ];
+            let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+            assert_eq!(
+                Processor::process_update_sol_recipient(&program_id, &infos),
+                Err(TokenError::InvalidInstruction.into())
+            );
+        }
+
+        let new_recipient = Pubkey::new_unique();
+        let mut accounts = [
+            TestAccount::signer(stream.recipient),
+            TestAccount::new(new_recipient, system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), program_id, stream_data(&stream)),
+        ];
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_update_sol_recipient(&program_id, &infos).unwrap();
+        assert_eq!(Stream::load(&infos[2]).unwrap(), Stream { recipient: new_recipient, ..stream });
+    }
+
+    #[test]
+    fn sender_update_needs_the_new_sender_signature() {
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = [
+            TestAccount::signer(stream.sender),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), program_id, stream_data(&stream)),
+            TestAccount::new(Pubkey::new_unique(), program_id, vec![]),
+            TestAccount::new(Pubkey::new_unique(), program_id, vec![]),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+        ];
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_update_sol_sender(&program_id, &infos),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    // sender, new sender, both master pdas, stream data, both withdraw data and system program
+    // accounts of a solana sender update, 700 reserved by the sender and `new_reserved` by the new sender
+    fn sender_update_accounts(program_id: &Pubkey, stream_data: Vec<u8>, sender: Pubkey, new_sender: Pubkey, new_reserved: u64) -> Vec<TestAccount> {
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let (pda, _) = get_master_address_and_bump_seed(&sender, program_id);
+        let (new_pda, _) = get_master_address_and_bump_seed(&new_sender, program_id);
+        let (withdraw_data, _) = get_withdraw_data_and_bump_seed(PREFIX, &sender, program_id);
+        let (new_withdraw_data, _) = get_withdraw_data_and_bump_seed(PREFIX, &new_sender, program_id);
+        vec![
+            TestAccount::signer(sender),
+            TestAccount::signer(new_sender),
+            TestAccount::new(pda, system_program::id(), vec![]),
+            TestAccount::new(new_pda, system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), *program_id, stream_data),
+            TestAccount::new(withdraw_data, *program_id, Withdraw { amount: 700 }.try_to_vec().unwrap()),
+            TestAccount::new(new_withdraw_data, *program_id, Withdraw { amount: new_reserved }.try_to_vec().unwrap()),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+        ]
+    }
+
+    #[test]
+    fn sender_update_moves_the_locked_funds_and_reservation() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.withdrawn = 300;
+        let new_sender = Pubkey::new_unique();
+        let mut accounts = sender_update_accounts(&program_id, stream_data(&stream), stream.sender, new_sender, 200);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_update_sol_sender(&program_id, &infos).unwrap();
+        assert_eq!(invoked(), vec![system_instruction::transfer(infos[2].key, infos[3].key, 700)]);
+        assert_eq!(Withdraw::try_from_slice(&infos[5].data.borrow()).unwrap().amount, 0);
+        assert_eq!(Withdraw::try_from_slice(&infos[6].data.borrow()).unwrap().amount, 900);
+        // The original sender paid the rent, so it still gets it back
+        let escrow = Stream::load(&infos[4]).unwrap();
+        assert_eq!((escrow.sender, escrow.rent_payer), (new_sender, stream.sender));
+    }
+
+    #[test]
+    fn sender_update_rejects_the_recipient() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.withdrawn = 300;
+        let mut accounts = sender_update_accounts(&program_id, stream_data(&stream), stream.sender, stream.recipient, 0);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_update_sol_sender(&program_id, &infos),
+            Err(TokenError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn sender_update_migrates_legacy_streams() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.withdrawn = 300;
+        let new_sender = Pubkey::new_unique();
+        let legacy = stream.to_legacy().try_to_vec().unwrap();
+        let mut accounts = sender_update_accounts(&program_id, legacy, stream.sender, new_sender, 0);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_update_sol_sender(&program_id, &infos).unwrap();
+        assert_eq!(Withdraw::try_from_slice(&infos[6].data.borrow()).unwrap().amount, 700);
+        // Legacy layout cannot record the rent payer, the rent follows the sender
+        assert!(Stream::is_legacy(&infos[4]));
+        let escrow = Stream::load(&infos[4]).unwrap();
+        assert_eq!((escrow.sender, escrow.rent_payer), (new_sender, Pubkey::default()));
+    }
+
+    #[test]
+    fn multisig_stream_rejects_bad_pause_terms_and_metadata() {
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let program_id = Pubkey::new_unique();
+        let mut accounts = [
+            TestAccount::signer(Pubkey::new_unique()),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), program_id, vec![]),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+        ];
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        let data = EscrowMultisig {
+            start_time: NOW,
+            end_time: NOW + 100,
+            paused: 0,
+            withdraw_limit: 0,
+            amount: 1000,
+            sender: *infos[0].key,
+            recipient: *infos[1].key,
+            signed_by: vec![],
+            multisig_safe: Pubkey::new_unique(),
+            can_cancel: true,
+        };
+        let mut metadata = StreamMetadata::default();
+        assert_eq!(
+            Processor::process_sol_stream_multisig(&program_id, &infos, data.clone(), false, crate::PAUSE_NONE + 1, false, metadata),
+            Err(TokenError::InvalidInstruction.into())
+        );
+        metadata.memo = [0xff; 64];
+        assert_eq!(
+            Processor::process_sol_stream_multisig(&program_id, &infos, data, false, crate::PAUSE_NONE, false, metadata),
+            Err(TokenError::InvalidInstruction.into())
+        );
+    }
+
+    // recipient, system program and fee accounts of a solana batch withdraw, followed by the sender,
+    // master pda, stream data and withdraw data of every stream with the amount reserved for it
+    fn sol_batch_accounts(program_id: &Pubkey, recipient: Pubkey, streams: &[(&Stream, u64)]) -> Vec<TestAccount> {
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let fee_receiver = Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        let mut accounts = vec![
+            TestAccount::signer(recipient),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+            TestAccount::new(fee_receiver, system_program::id(), vec![]),
+        ];
+        for (stream, reserved) in streams {
+            let (pda, _) = get_master_address_and_bump_seed(&stream.sender, program_id);
+            let (withdraw_data, _) = get_withdraw_data_and_bump_seed(PREFIX, &stream.sender, program_id);
+            accounts.extend([
+                TestAccount::new(stream.sender, system_program::id(), vec![]),
+                TestAccount::new(pda, system_program::id(), vec![]),
+                TestAccount::new(Pubkey::new_unique(), *program_id, stream_data(*stream)),
+                TestAccount::new(withdraw_data, *program_id, Withdraw { amount: *reserved }.try_to_vec().unwrap()),
+            ]);
+        }
+        accounts
+    }
+
+    #[test]
+    fn batch_withdraw_keeps_a_renewal_with_nothing_to_pay() {
+        let program_id = Pubkey::new_unique();
+        // Renewable stream whose first period just ended and was withdrawn in full
+        let stream = Stream {
+            start_time: NOW - 100,
+            end_time: NOW,
+            amount: 1000,
+            withdrawn: 1000,
+            sender: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            release_interval: 100,
+            renewable: true,
+            periods: 1,
+            ..Default::default()
+        };
+        let mut accounts = sol_batch_accounts(&program_id, stream.recipient, &[(&stream, 0)]);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_sol_batch_withdraw(&program_id, &infos).unwrap();
+        let escrow = Stream::load(&infos[5]).unwrap();
+        assert_eq!((escrow.end_time, escrow.amount, escrow.periods), (NOW + 100, 2000, 2));
+        assert_eq!(Withdraw::try_from_slice(&infos[6].data.borrow()).unwrap().amount, 1000);
+        // Second period is already covered, so it is not reserved again
+        Processor::process_sol_batch_withdraw(&program_id, &infos).unwrap();
+        assert_eq!(Stream::load(&infos[5]).unwrap().amount, 2000);
+        assert_eq!(Withdraw::try_from_slice(&infos[6].data.borrow()).unwrap().amount, 1000);
+    }
+
+    #[test]
+    fn batch_withdraw_pays_streams_of_several_senders_with_one_fee_transfer() {
+        let program_id = Pubkey::new_unique();
+        let mut first = running_stream();
+        first.amount = 100_000;
+        let second = Stream {
+            start_time: NOW - 25,
+            end_time: NOW + 75,
+            amount: 200_000,
+            sender: Pubkey::new_unique(),
+            recipient: first.recipient,
+            periods: 1,
+            ..Default::default()
+        };
+        let mut accounts = sol_batch_accounts(&program_id, first.recipient, &[(&first, 100_000), (&second, 200_000)]);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_sol_batch_withdraw(&program_id, &infos).unwrap();
+        assert_eq!(Stream::load(&infos[5]).unwrap().withdrawn, 50_000);
+        assert_eq!(Withdraw::try_from_slice(&infos[6].data.borrow()).unwrap().amount, 50_000);
+        assert_eq!(Stream::load(&infos[9]).unwrap().withdrawn, 50_000);
+        assert_eq!(Withdraw::try_from_slice(&infos[10].data.borrow()).unwrap().amount, 150_000);
+        assert_eq!(invoked(), vec![
+            system_instruction::transfer(infos[4].key, &first.recipient, 50_000),
+            system_instruction::transfer(infos[8].key, &first.recipient, 50_000),
+            system_instruction::transfer(&first.recipient, infos[2].key, 250),
+        ]);
+    }
+
+    #[test]
+    fn batch_withdraw_skips_streams_with_nothing_to_withdraw() {
+        let program_id = Pubkey::new_unique();
+        let first = running_stream();
+        let mut second = running_stream();
+        second.recipient = first.recipient;
+        second.withdrawn = 500;
+        let mut accounts = sol_batch_accounts(&program_id, first.recipient, &[(&first, 1000), (&second, 500)]);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_sol_batch_withdraw(&program_id, &infos).unwrap();
+        assert_eq!(Stream::load(&infos[5]).unwrap().withdrawn, 500);
+        assert_eq!(Stream::load(&infos[9]).unwrap(), second);
+        assert_eq!(Withdraw::try_from_slice(&infos[10].data.borrow()).unwrap().amount, 500);
+        assert_eq!(invoked(), vec![
+            system_instruction::transfer(infos[4].key, &first.recipient, 500),
+            system_instruction::transfer(&first.recipient, infos[2].key, 1),
+        ]);
+    }
+
+    #[test]
+    fn batch_withdraw_rejects_tokenized_and_split_streams() {
+        let program_id = Pubkey::new_unique();
+        let first = running_stream();
+        let mut tokenized = running_stream();
+        tokenized.recipient = first.recipient;
+        tokenized.receivable_mint = Pubkey::new_unique();
+        let mut split = running_stream();
+        split.recipient = first.recipient;
+        split.beneficiaries = vec![Beneficiary { address: Pubkey::new_unique(), share_bps: 10000, withdrawn: 0 }];
+        for stream in [&tokenized, &split] {
+            let mut accounts = sol_batch_accounts(&program_id, first.recipient, &[(&first, 1000), (stream, 1000)]);
+            let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+            assert_eq!(
+                Processor::process_sol_batch_withdraw(&program_id, &infos),
+                Err(TokenError::EscrowMismatch.into())
+            );
+        }
+    }
+
+    // recipient, receiver token, token program, mint, rent, associated token program, system program,
+    // fee and fee token accounts of a token batch withdraw, followed by the sender, master pda, stream
+    // data, withdraw data and pda token account of every stream with the amount reserved for it
+    fn token_batch_accounts(program_id: &Pubkey, recipient: Pubkey, mint: Pubkey, streams: &[(&StreamToken, u64)]) -> Vec<TestAccount> {
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let fee_receiver = Pubkey::from_str("EsDV3m3xUZ7g8QKa1kFdbZT18nNz8ddGJRcTK84WDQ7k").unwrap();
+        let mut accounts = vec![
+            TestAccount::signer(recipient),
+            TestAccount::new(get_associated_token_address(&recipient, &mint), spl_token::id(), vec![]),
+            TestAccount::new(spl_token::id(), Pubkey::default(), vec![]),
+            TestAccount::new(mint, spl_token::id(), vec![]),
+            TestAccount::new(solana_program::sysvar::rent::id(), Pubkey::default(), vec![]),
+            TestAccount::new(spl_associated_token_account::id(), Pubkey::default(), vec![]),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+            TestAccount::new(fee_receiver, system_program::id(), vec![]),
+            TestAccount::new(get_associated_token_address(&fee_receiver, &mint), spl_token::id(), vec![]),
+        ];
+        for (stream, reserved) in streams {
+            let (pda, _) = get_master_address_and_bump_seed(&stream.sender, program_id);
+            let (withdraw_data, _) = get_token_withdraw_data_and_bump_seed(PREFIX_TOKEN, &stream.sender, &mint, program_id);
+            accounts.extend([
+                TestAccount::new(stream.sender, system_program::id(), vec![]),
+                TestAccount::new(pda, system_program::id(), vec![]),
+                TestAccount::new(Pubkey::new_unique(), *program_id, stream_data(*stream)),
+                TestAccount::new(withdraw_data, *program_id, TokenWithdraw { amount: *reserved }.try_to_vec().unwrap()),
+                TestAccount::new(get_associated_token_address(&pda, &mint), spl_token::id(), vec![]),
+            ]);
+        }
+        accounts
+    }
+
+    #[test]
+    fn token_batch_withdraw_pays_streams_of_several_senders_with_one_fee_transfer() {
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let stream = |sender| StreamToken {
+            start_time: NOW - 50,
+            end_time: NOW + 50,
+            amount: 100_000,
+            sender,
+            recipient,
+            token_mint: mint,
+            periods: 1,
+            ..Default::default()
+        };
+        let (first, second) = (stream(Pubkey::new_unique()), stream(Pubkey::new_unique()));
+        let mut accounts = token_batch_accounts(&program_id, recipient, mint, &[(&first, 100_000), (&second, 100_000)]);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_token_batch_withdraw(&program_id, &infos).unwrap();
+        for (data, withdraw_data) in [(11, 12), (16, 17)] {
+            assert_eq!(StreamToken::load(&infos[data]).unwrap().withdrawn, 50_000);
+            assert_eq!(TokenWithdraw::try_from_slice(&infos[withdraw_data].data.borrow()).unwrap().amount, 50_000);
+        }
+        let transfer = |from: usize, to: usize, authority: &Pubkey, amount| {
+            spl_token::instruction::transfer(&spl_token::id(), infos[from].key, infos[to].key, authority, &[authority], amount).unwrap()
+        };
+        let transfers: Vec<Instruction> = invoked().into_iter().filter(|ix| ix.program_id == spl_token::id()).collect();
+        assert_eq!(transfers, vec![
+            transfer(13, 1, infos[10].key, 50_000),
+            transfer(18, 1, infos[15].key, 50_000),
+            transfer(1, 8, &recipient, 250),
+        ]);
+    }
+
+    // authority, rent payer and stream data accounts of a close
+    fn close_accounts(program_id: &Pubkey, authority: Pubkey, rent_payer: Pubkey, stream_data: Vec<u8>) -> Vec<TestAccount> {
+        vec![
+            TestAccount::signer(authority),
+            TestAccount::new(rent_payer, system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), *program_id, stream_data),
+        ]
+    }
+
+    fn finished_token_stream() -> StreamToken {
+        let stream = running_stream();
+        StreamToken {
+            start_time: stream.start_time,
+            end_time: stream.end_time,
+            amount: 1000,
+            withdrawn: 1000,
+            sender: stream.sender,
+            recipient: stream.recipient,
+            token_mint: Pubkey::new_unique(),
+            periods: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn close_needs_a_finished_stream() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let mut accounts = close_accounts(&program_id, stream.sender, stream.sender, stream_data(&stream));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_close_sol_stream(&program_id, &infos), Err(TokenError::StreamNotFinished.into()));
+
+        let token_stream = StreamToken { withdrawn: 500, ..finished_token_stream() };
+        let mut accounts = close_accounts(&program_id, token_stream.sender, token_stream.sender, stream_data(&token_stream));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_close_token_stream(&program_id, &infos), Err(TokenError::StreamNotFinished.into()));
+
+        let multisig_stream = StreamMultisig { amount: 1000, withdrawn: 500, sender: stream.sender, ..Default::default() };
+        let mut accounts = close_accounts(&program_id, stream.sender, stream.sender, stream_data(&multisig_stream));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_close_sol_multisig_stream(&program_id, &infos), Err(TokenError::StreamNotFinished.into()));
+
+        let token_multisig_stream = TokenStreamMultisig { amount: 1000, withdrawn: 500, sender: stream.sender, ..Default::default() };
+        let mut accounts = close_accounts(&program_id, stream.sender, stream.sender, stream_data(&token_multisig_stream));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_close_token_multisig_stream(&program_id, &infos), Err(TokenError::StreamNotFinished.into()));
+    }
+
+    #[test]
+    fn close_returns_the_rent_to_its_payer() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.withdrawn = 1000;
+        stream.rent_payer = Pubkey::new_unique();
+        let mut accounts = close_accounts(&program_id, stream.sender, stream.sender, stream_data(&stream));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_close_sol_stream(&program_id, &infos), Err(TokenError::PublicKeyMismatch.into()));
+
+        let multisig_stream = TokenStreamMultisig { amount: 1000, withdrawn: 1000, sender: stream.sender, ..Default::default() };
+        let mut accounts = close_accounts(&program_id, stream.sender, stream.rent_payer, stream_data(&multisig_stream));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_close_token_multisig_stream(&program_id, &infos), Err(TokenError::PublicKeyMismatch.into()));
+
+        // Recipient closing a stream someone else paid for
+        let mut accounts = close_accounts(&program_id, stream.recipient, stream.rent_payer, stream_data(&stream));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_close_sol_stream(&program_id, &infos).unwrap();
+        assert_eq!((infos[1].lamports(), infos[2].lamports()), (2_000_000, 0));
+        assert!(infos[2].data.borrow().iter().all(|byte| *byte == 0));
+
+        let multisig_stream = StreamMultisig { amount: 1000, withdrawn: 1000, sender: stream.sender, recipient: stream.recipient, ..Default::default() };
+        let mut accounts = close_accounts(&program_id, stream.recipient, stream.sender, stream_data(&multisig_stream));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_close_sol_multisig_stream(&program_id, &infos).unwrap();
+        assert_eq!((infos[1].lamports(), infos[2].lamports()), (2_000_000, 0));
+        assert!(infos[2].data.borrow().iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn sender_close_also_closes_unused_withdraw_data() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.withdrawn = 1000;
+        let (pda, _) = get_master_address_and_bump_seed(&stream.sender, &program_id);
+        let (withdraw_data, _) = get_withdraw_data_and_bump_seed(PREFIX, &stream.sender, &program_id);
+        for (vault_lamports, reserved, closed) in [(0, 0, true), (0, 100, false), (100, 0, false)] {
+            let mut accounts = close_accounts(&program_id, stream.sender, stream.sender, stream_data(&stream));
+            accounts.extend([
+                TestAccount { lamports: vault_lamports, ..TestAccount::new(pda, system_program::id(), vec![]) },
+                TestAccount::new(withdraw_data, program_id, Withdraw { amount: reserved }.try_to_vec().unwrap()),
+            ]);
+            let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+            Processor::process_close_sol_stream(&program_id, &infos).unwrap();
+            assert_eq!(infos[4].lamports() == 0, closed);
+        }
+
+        // Recipient cannot close the sender's withdraw data
+        let mut accounts = close_accounts(&program_id, stream.recipient, stream.sender, stream_data(&stream));
+        accounts.extend([
+            TestAccount { lamports: 0, ..TestAccount::new(pda, system_program::id(), vec![]) },
+            TestAccount::new(withdraw_data, program_id, Withdraw { amount: 0 }.try_to_vec().unwrap()),
+        ]);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(Processor::process_close_sol_stream(&program_id, &infos), Err(TokenError::OwnerMismatch.into()));
+    }
+
+    #[test]
+    fn sender_close_also_closes_an_empty_token_vault() {
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let program_id = Pubkey::new_unique();
+        let stream = finished_token_stream();
+        let (pda, _) = get_master_address_and_bump_seed(&stream.sender, &program_id);
+        let (withdraw_data, _) = get_token_withdraw_data_and_bump_seed(PREFIX_TOKEN, &stream.sender, &stream.token_mint, &program_id);
+        let pda_associated = get_associated_token_address(&pda, &stream.token_mint);
+        let mut accounts = close_accounts(&program_id, stream.sender, stream.sender, stream_data(&stream));
+        accounts.extend([
+            TestAccount::new(pda, system_program::id(), vec![]),
+            TestAccount::new(withdraw_data, program_id, TokenWithdraw { amount: 0 }.try_to_vec().unwrap()),
+            TestAccount::new(pda_associated, spl_token::id(), vec![0; spl_token::state::Account::LEN]),
+            TestAccount::new(spl_token::id(), Pubkey::default(), vec![]),
+        ]);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_close_token_stream(&program_id, &infos).unwrap();
+        assert_eq!((infos[0].lamports(), infos[2].lamports(), infos[4].lamports()), (2_000_000, 0, 0));
+        assert_eq!(invoked(), vec![
+            spl_token::instruction::close_account(&spl_token::id(), &pda_associated, &stream.sender, &pda, &[]).unwrap(),
+        ]);
+    }
+
+    #[test]
+    fn sol_beneficiary_withdraw_only_needs_a_signature_when_the_stream_asks() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        let beneficiary = Pubkey::new_unique();
+        stream.beneficiaries = vec![
+            Beneficiary { address: beneficiary, share_bps: 4000, withdrawn: 0 },
+            Beneficiary { address: Pubkey::new_unique(), share_bps: 6000, withdrawn: 0 },
+        ];
+        for recipient_must_sign in [false, true] {
+            stream.recipient_must_sign = recipient_must_sign;
+            let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+            accounts[1] = TestAccount::new(beneficiary, system_program::id(), vec![]);
+            let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+            if recipient_must_sign {
+                assert_eq!(
+                    Processor::process_sol_beneficiary_withdraw(&program_id, &infos),
+                    Err(ProgramError::MissingRequiredSignature)
+                );
+            } else {
+                Processor::process_sol_beneficiary_withdraw(&program_id, &infos).unwrap();
+                let escrow = Stream::load(&infos[3]).unwrap();
+                assert_eq!((escrow.beneficiaries[0].withdrawn, escrow.withdrawn), (200, 200));
+            }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn tokenized_stream_mints_at_the_address_derived_from_the_stream() {
+        let program_id = Pubkey::new_unique();
+        let (sender, recipient, stream_key) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (withdraw_data, _) = get_withdraw_data_and_bump_seed(PREFIX, &sender, &program_id);
+        let (mint, _) = get_receivable_mint_and_bump_seed(PREFIX_RECEIVABLE, &stream_key, &program_id);
+        let mut terms = StreamTerms { start_time: NOW + 10, end_time: NOW + 110, amount: 1000, tokenized: true, ..Default::default() };
+        let mut accounts = [
+            TestAccount::signer(sender),
+            TestAccount::new(recipient, system_program::id(), vec![]),
+            TestAccount::new(stream_key, system_program::id(), vec![]),
+            TestAccount::new(withdraw_data, program_id, Withdraw { amount: 0 }.try_to_vec().unwrap()),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(spl_token::id(), Pubkey::default(), vec![]),
+            TestAccount::new(spl_associated_token_account::id(), Pubkey::default(), vec![]),
+            TestAccount::new(solana_program::sysvar::rent::id(), Pubkey::default(), vec![]),
+        ];
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+
+        // Split streams still cannot be tokenized
+        terms.beneficiaries = vec![
+            Beneficiary { address: Pubkey::new_unique(), share_bps: 5000, withdrawn: 0 },
+            Beneficiary { address: Pubkey::new_unique(), share_bps: 5000, withdrawn: 0 },
+        ];
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_vesting_stream(&program_id, &infos, terms.clone()),
+            Err(TokenError::InvalidInstruction.into())
+        );
+        terms.beneficiaries = vec![];
+
+        // Only the mint derived from the stream address is created
+        assert_eq!(
+            Processor::process_sol_vesting_stream(&program_id, &infos, terms.clone()),
+            Err(TokenError::PublicKeyMismatch.into())
+        );
+        drop(infos);
+        accounts[5].key = mint;
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        // Stubbed system program never allocates the stream, so only the mint instructions are checked
+        assert_eq!(
+            Processor::process_sol_vesting_stream(&program_id, &infos, terms),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        assert!(invoked().iter().any(|ix| ix.program_id == spl_token::id() && ix.accounts[0].pubkey == mint));
+    }
+
+    #[test]
+    fn derived_stream_is_only_used_at_its_address() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        let (address, bump) = get_stream_address_and_bump_seed(&stream.sender, &stream.recipient, &Pubkey::default(), 7, &program_id);
+        stream.seeds = StreamSeeds { sender: stream.sender, recipient: stream.recipient, nonce: 7, bump: 0 };
+
+        // Creation only derives the stream at its own address
+        let (withdraw_data, _) = get_withdraw_data_and_bump_seed(PREFIX, &stream.sender, &program_id);
+        let mut accounts = [
+            TestAccount::signer(stream.sender),
+            TestAccount::new(stream.recipient, system_program::id(), vec![]),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+            TestAccount::new(withdraw_data, program_id, Withdraw { amount: 0 }.try_to_vec().unwrap()),
+            TestAccount::new(system_program::id(), Pubkey::default(), vec![]),
+        ];
+        program_stubs::set_syscall_stubs(Box::new(ClockStubs));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_vesting_stream(&program_id, &infos, StreamTerms {
+                start_time: stream.start_time,
+                end_time: stream.end_time,
+                amount: stream.amount,
+                nonce: Some(7),
+                ..Default::default()
+            }),
+            Err(TokenError::PublicKeyMismatch.into())
+        );
+
+        // Created stream records the bump of its address
+        stream.seeds.bump = bump;
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[3].key = Pubkey::new_unique();
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_withdraw_stream(&program_id, &infos, 100),
+            Err(TokenError::PublicKeyMismatch.into())
+        );
+        assert_eq!(
+            Processor::process_cancel_sol_stream(&program_id, &infos),
+            Err(TokenError::PublicKeyMismatch.into())
+        );
+
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[3].key = address;
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_sol_withdraw_stream(&program_id, &infos, 100).unwrap();
+        assert_eq!(Stream::load(&infos[3]).unwrap().withdrawn, 100);
+    }
+
+    #[test]
+    fn sol_withdraw_to_pays_the_destination_chosen_by_the_recipient() {
+        let program_id = Pubkey::new_unique();
+        let stream = running_stream();
+        let destination = Pubkey::new_unique();
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts.push(TestAccount::new(destination, system_program::id(), vec![]));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_sol_withdraw_stream_to(&program_id, &infos, 400).unwrap();
+        assert_eq!(invoked(), vec![
+            system_instruction::transfer(infos[2].key, infos[6].key, 1),
+            system_instruction::transfer(infos[2].key, &destination, 399),
+        ]);
+        assert_eq!(Stream::load(&infos[3]).unwrap().withdrawn, 400);
+
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        accounts[1].is_signer = false;
+        accounts.push(TestAccount::new(destination, system_program::id(), vec![]));
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_sol_withdraw_stream_to(&program_id, &infos, 400),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn token_withdraw_to_needs_a_token_account_of_the_stream_mint() {
+        let program_id = Pubkey::new_unique();
+        let stream = StreamToken { withdrawn: 0, ..finished_token_stream() };
+        let token_account = |mint| {
+            let mut data = vec![0; spl_token::state::Account::LEN];
+            let account = spl_token::state::Account {
+                mint,
+                owner: Pubkey::new_unique(),
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            };
+            spl_token::state::Account::pack(account, &mut data).unwrap();
+            TestAccount::new(Pubkey::new_unique(), spl_token::id(), data)
+        };
+        let receiver_token = get_associated_token_address(&stream.recipient, &stream.token_mint);
+        // Token withdraw takes the accounts of a crank without the keeper and its token account
+        let withdraw_to_accounts = |destination: TestAccount, recipient_signs: bool| {
+            let mut accounts = token_crank_accounts(&program_id, &stream, receiver_token).split_off(2);
+            accounts[1].is_signer = recipient_signs;
+            accounts.push(destination);
+            accounts
+        };
+
+        let mut accounts = withdraw_to_accounts(token_account(Pubkey::new_unique()), true);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_token_withdraw_stream_to(&program_id, &infos, 400),
+            Err(TokenError::PublicKeyMismatch.into())
+        );
+
+        let mut accounts = withdraw_to_accounts(token_account(stream.token_mint), false);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_token_withdraw_stream_to(&program_id, &infos, 400),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        let mut accounts = withdraw_to_accounts(token_account(stream.token_mint), true);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        Processor::process_token_withdraw_stream_to(&program_id, &infos, 400).unwrap();
+        let payout = spl_token::instruction::transfer(&spl_token::id(), infos[8].key, infos[14].key, infos[2].key, &[infos[2].key], 399).unwrap();
+        assert_eq!(invoked().last(), Some(&payout));
+        assert_eq!(StreamToken::load(&infos[3]).unwrap().withdrawn, 400);
+    }
+
+    #[test]
+    fn cancel_follows_the_cancel_policy() {
+        let program_id = Pubkey::new_unique();
+        let mut stream = running_stream();
+        stream.cancel_policy = crate::CANCEL_BY_NOBODY;
+        let mut accounts = withdraw_accounts(&program_id, &stream, stream.sender);
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(|a| a.info()).collect();
+        assert_eq!(
+            Processor::process_cancel_sol_stream(&program_id, &infos),
+            Err(TokenError::CancelNotAllowed.into())
+        );
+    }
+}
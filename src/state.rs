@@ -5,8 +5,634 @@ use solana_program::{
     program_error::{ProgramError},
     account_info:: AccountInfo,
     borsh::try_from_slice_unchecked,
+    entrypoint::ProgramResult,
 };
+use crate::{
+    error::TokenError,
+    utils::{assert_valid_beneficiaries, assert_valid_breakpoints, assert_valid_metadata, assert_valid_pause_terms, calculate_vested_amount},
+    CANCEL_BY_NOBODY,
+    MAX_SIGNERS,
+    STREAM_MAGIC,
+    STREAM_VERSION,
+    STREAM_HEADER_LEN,
+    KIND_SOL_STREAM,
+    KIND_TOKEN_STREAM,
+    KIND_SOL_MULTISIG_STREAM,
+    KIND_TOKEN_MULTISIG_STREAM,
+};
+
+/// Vesting, pause and cancel rules shared by every stream account
+pub trait StreamSchedule {
+    /// Total amount unlocked at `now`, frozen while the stream is paused
+    fn vested_amount(&self, now: u64) -> Result<u64, ProgramError>;
+    /// Amount already paid out to the recipient
+    fn withdrawn_amount(&self) -> u64;
+    /// Total amount locked in the stream
+    fn stream_amount(&self) -> u64;
+    fn pause(&mut self, now: u64) -> ProgramResult;
+    fn resume(&mut self, now: u64) -> ProgramResult;
+
+    /// Amount the recipient can withdraw at `now`
+    fn withdrawable_amount(&self, now: u64) -> Result<u64, ProgramError> {
+        // Forfeited pauses round the curve down, so vested can fall just below withdrawn
+        Ok(self.vested_amount(now)?.saturating_sub(self.withdrawn_amount()))
+    }
+    /// Split on cancel: (owed to the recipient, returned to the sender)
+    fn cancel_split(&self, now: u64) -> Result<(u64, u64), ProgramError> {
+        let recipient_amt = self.withdrawable_amount(now)?;
+        // Nothing is reserved for open-ended streams, so the sender gets nothing back
+        let sender_amt = self.stream_amount()
+            .saturating_sub(self.withdrawn_amount())
+            .saturating_sub(recipient_amt);
+        Ok((recipient_amt, sender_amt))
+    }
+}
+
+/// Stream data stored behind a version header. Accounts created before the header keep
+/// their legacy layout, they are read and written in it for as long as the stream fits.
+pub trait StreamAccount: BorshSerialize + BorshDeserialize + PartialEq + Sized {
+    /// Kind byte of the header, tells the stream account types apart
+    const KIND: u8;
+    /// Length of the accounts created before the header
+    const LEGACY_LEN: usize;
+    type Legacy: BorshSerialize + BorshDeserialize;
+    fn from_legacy(legacy: Self::Legacy) -> Self;
+    fn to_legacy(&self) -> Self::Legacy;
+
+    /// Length of a new account holding this stream
+    fn account_len(&self) -> Result<usize, ProgramError> {
+        Ok(STREAM_HEADER_LEN + self.try_to_vec()?.len())
+    }
+    /// True when `account` holds a stream of this kind, in either layout
+    fn is_kind(account: &AccountInfo) -> bool {
+        let data = account.data.borrow();
+        match stream_header(&data) {
+            Some((version, kind)) => version == STREAM_VERSION && kind == Self::KIND,
+            None => data.len() == Self::LEGACY_LEN,
+        }
+    }
+    /// True when `account` still has the layout from before the header
+    fn is_legacy(account: &AccountInfo) -> bool {
+        stream_header(&account.data.borrow()).is_none()
+    }
+    fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        if !Self::is_kind(account) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let data = account.data.borrow();
+        if stream_header(&data).is_some() {
+            return Ok(Self::deserialize(&mut &data[STREAM_HEADER_LEN..])?);
+        }
+        Ok(Self::from_legacy(Self::Legacy::deserialize(&mut &data[..])?))
+    }
+    fn save(&self, account: &AccountInfo) -> ProgramResult {
+        let mut data = account.data.borrow_mut();
+        if stream_header(&data).is_some() {
+            return Ok(self.serialize(&mut &mut data[STREAM_HEADER_LEN..])?);
+        }
+        // Anything the legacy layout drops would be lost on the next load
+        let legacy = self.to_legacy();
+        if Self::from_legacy(Self::Legacy::try_from_slice(&legacy.try_to_vec()?)?) != *self {
+            return Err(TokenError::LegacyLayout.into());
+        }
+        Ok(legacy.serialize(&mut &mut data[..])?)
+    }
+    /// Writes the header and the stream into a new account
+    fn init(&self, account: &AccountInfo) -> ProgramResult {
+        let mut data = account.data.borrow_mut();
+        if data.len() < STREAM_HEADER_LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[..6].copy_from_slice(&STREAM_MAGIC);
+        data[6] = STREAM_VERSION;
+        data[7] = Self::KIND;
+        Ok(self.serialize(&mut &mut data[STREAM_HEADER_LEN..])?)
+    }
+}
+/// Version and kind of a stream account with a header. Legacy accounts start with start_time,
+/// which never matches the magic.
+pub fn stream_header(data: &[u8]) -> Option<(u8, u8)> {
+    if data.len() < STREAM_HEADER_LEN || data[..6] != STREAM_MAGIC {
+        return None;
+    }
+    Some((data[6], data[7]))
+}
+/// Conversions between a stream and the legacy layout holding its first fields
+macro_rules! impl_stream_account {
+    ($stream:ty, $legacy:ident, $kind:expr, $legacy_len:expr, [$($field:ident),*] $($extra:tt)*) => {
+        impl StreamAccount for $stream {
+            const KIND: u8 = $kind;
+            const LEGACY_LEN: usize = $legacy_len;
+            type Legacy = $legacy;
+            fn from_legacy(legacy: $legacy) -> Self {
+                Self {
+                    $($field: legacy.$field,)*
+                    ..Default::default()
+                }
+            }
+            fn to_legacy(&self) -> $legacy {
+                $legacy {
+                    $($field: self.$field.clone(),)*
+                }
+            }
+            $($extra)*
+        }
+    };
+}
+/// Multisig streams were created with 355 spare bytes after their fixed fields
+macro_rules! impl_multisig_account {
+    ($stream:ty, $legacy:ident, $kind:expr, [$($field:ident),*]) => {
+        impl_stream_account!($stream, $legacy, $kind, std::mem::size_of::<$legacy>() + 355, [$($field),*]
+            // Signers are added after creation, so the account keeps room for all of them
+            fn account_len(&self) -> Result<usize, ProgramError> {
+                let unsigned = MAX_SIGNERS.saturating_sub(self.signed_by.len());
+                Ok(STREAM_HEADER_LEN + self.try_to_vec()?.len() + unsigned * WhiteList::LEN)
+            }
+        );
+    };
+}
+/// Forfeiting resume of every stream that records `paused_at`
+macro_rules! impl_resume_forfeit {
+    ($stream:ty) => {
+        impl $stream {
+            /// Resumes without moving end_time, so the time spent paused is never streamed.
+            /// Returns the amount forfeited back to the sender.
+            pub fn resume_forfeit(&mut self, now: u64) -> Result<u64, ProgramError> {
+                if self.paused == 0 || self.paused_at == 0 {
+                    return Err(TokenError::AlreadyResumed.into());
+                }
+                let vested = self.vested_amount(now)?;
+                let amount = if now >= self.end_time {
+                    vested
+                } else {
+                    let time_spent = now.checked_sub(self.paused_at).ok_or(TokenError::Overflow)?;
+                    let start_time = self.start_time.checked_add(time_spent).ok_or(TokenError::Overflow)?;
+                    // Same rate as before over what is left of the stream
+                    let amount = (self.amount as u128)
+                        .checked_mul((self.end_time - start_time) as u128)
+                        .and_then(|amt| amt.checked_div((self.end_time - self.start_time) as u128))
+                        .ok_or(TokenError::Overflow)?;
+                    self.start_time = start_time;
+                    (amount as u64).max(vested)
+                };
+                let forfeited = self.amount.checked_sub(amount).ok_or(TokenError::Overflow)?;
+                self.amount = amount;
+                self.paused = 0;
+                self.paused_at = 0;
+                Ok(forfeited)
+            }
+        }
+    };
+}
+/// Schedule of the legacy layouts, which subtract withdrawals from amount
+macro_rules! impl_legacy_schedule {
+    ($stream:ty) => {
+        impl StreamSchedule for $stream {
+            fn vested_amount(&self, now: u64) -> Result<u64, ProgramError> {
+                calculate_vested_amount(self.start_time, self.end_time, 0, self.amount, now)
+            }
+            // Legacy layout subtracts withdrawals from amount instead of tracking them
+            fn withdrawn_amount(&self) -> u64 {
+                0
+            }
+            fn stream_amount(&self) -> u64 {
+                self.amount
+            }
+            fn withdrawable_amount(&self, now: u64) -> Result<u64, ProgramError> {
+                let vested = self.vested_amount(now)?;
+                if self.paused == 1 {
+                    return Ok(vested.min(self.withdraw_limit));
+                }
+                Ok(vested)
+            }
+            fn pause(&mut self, now: u64) -> ProgramResult {
+                if self.paused == 1 {
+                    return Err(TokenError::AlreadyPaused.into());
+                }
+                self.withdraw_limit = self.vested_amount(now)?;
+                self.paused = 1;
+                Ok(())
+            }
+            fn resume(&mut self, _now: u64) -> ProgramResult {
+                if self.paused == 0 {
+                    return Err(TokenError::AlreadyResumed.into());
+                }
+                self.paused = 0;
+                self.withdraw_limit = 0;
+                Ok(())
+            }
+        }
+    };
+}
+/// Schedule of `Stream` and `StreamToken`, which share every schedule field
+macro_rules! impl_stream_schedule {
+    ($stream:ty) => {
+        impl $stream {
+            /// Vested amount interpolated between the breakpoints, (start_time, 0) and (end_time, amount)
+            fn breakpoint_amt(&self, now: u64) -> Result<u64, ProgramError> {
+                let mut prev_time = self.start_time;
+                let mut prev_amount = 0;
+                for point in self.breakpoints.iter() {
+                    if now < point.time {
+                        return calculate_vested_amount(prev_time, point.time, prev_amount, point.amount, now);
+                    }
+                    prev_time = point.time;
+                    prev_amount = point.amount;
+                }
+                calculate_vested_amount(prev_time, self.end_time, prev_amount, self.amount, now)
+            }
+
+            /// End time a new stream is created with, open-ended streams run until cancelled
+            pub fn effective_end_time(&self) -> u64 {
+                if self.rate != 0 { u64::MAX } else { self.end_time }
+            }
 
+            /// Checks the terms of a new stream between `sender` and `recipient`
+            pub fn assert_valid_terms(&self, sender: &Pubkey, recipient: &Pubkey, now: u64) -> ProgramResult {
+                let end_time = self.effective_end_time();
+                if sender == recipient {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                // Fixed streams must stream something, open-ended ones start empty
+                if self.rate == 0 && self.amount == 0 {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                if now >= end_time {
+                    return Err(TokenError::TimeEnd.into());
+                }
+                if self.start_time >= end_time {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                // Cliff must fall inside the stream and cannot unlock more than the stream amount
+                if self.cliff_time != 0 && (self.cliff_time < self.start_time || self.cliff_time >= end_time) {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                if self.cliff_amount > self.amount || (self.cliff_time == 0 && self.cliff_amount != 0) {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                if self.release_interval > end_time - self.start_time {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                // A custom curve already describes any cliff
+                if !self.breakpoints.is_empty() && self.cliff_time != 0 {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                // Renewal repeats the period at a constant rate
+                if self.renewable && (self.cliff_time != 0 || !self.breakpoints.is_empty()) {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                // Open-ended streams accrue linearly and reserve nothing up front
+                if self.rate != 0 && (self.amount != 0 || self.cliff_time != 0 || !self.breakpoints.is_empty() || self.renewable) {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                // Forfeiting keeps the rate of a linear stream over a shorter time
+                let linear = self.cliff_time == 0 && self.breakpoints.is_empty() && !self.renewable && self.rate == 0;
+                assert_valid_pause_terms(self.pause_policy, self.pause_forfeits, linear)?;
+                assert_valid_metadata(&self.metadata)?;
+                if self.cancel_policy > CANCEL_BY_NOBODY {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                // Split streams share a fixed amount between their beneficiaries
+                if !self.beneficiaries.is_empty() && (self.rate != 0 || self.receivable_mint != Pubkey::default()) {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                assert_valid_breakpoints(&self.breakpoints, self.start_time, end_time, self.amount)?;
+                assert_valid_beneficiaries(&self.beneficiaries)?;
+                // Arbiter is a neutral party settling a fixed amount
+                if self.arbiter != Pubkey::default() && (self.rate != 0 || self.arbiter == *sender || self.arbiter == *recipient) {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                // Keeper tip needs a crank interval and must stay below what one interval pays out
+                if self.crank_tip != 0 {
+                    let per_interval = if self.rate != 0 {
+                        self.rate.saturating_mul(self.crank_interval)
+                    } else {
+                        (self.amount as u128 * self.crank_interval as u128 / (end_time - self.start_time) as u128) as u64
+                    };
+                    if self.crank_interval == 0 || self.crank_tip >= per_interval {
+                        return Err(TokenError::InvalidInstruction.into());
+                    }
+                }
+                Ok(())
+            }
+
+            /// Checks that `party` can take over one side of the stream. Sender and recipient stay
+            /// apart and the arbiter stays neutral, as checked on creation.
+            pub fn assert_valid_new_party(&self, party: &Pubkey) -> ProgramResult {
+                if *party == self.sender || *party == self.recipient {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                if self.arbiter != Pubkey::default() && *party == self.arbiter {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                Ok(())
+            }
+
+            /// Part of a payout that was reserved in the sender's withdraw data,
+            /// open-ended streams have nothing reserved
+            pub fn reserved_part(&self, amount: u64) -> u64 {
+                if self.rate == 0 { amount } else { 0 }
+            }
+
+            /// Rolls a renewable stream over so it covers `now`, limited to the periods `free_amount`
+            /// can fund. Returns the amount to reserve for the new periods.
+            pub fn renew(&mut self, now: u64, free_amount: u64) -> Result<u64, ProgramError> {
+                if !self.renewable || self.paused == 1 || self.periods == 0 || now < self.end_time {
+                    return Ok(0);
+                }
+                let period = (self.end_time - self.start_time) / self.periods;
+                let period_amount = self.amount / self.periods;
+                let due = (now - self.end_time) / period + 1;
+                let count = match free_amount.checked_div(period_amount) {
+                    Some(affordable) => due.min(affordable),
+                    None => due,
+                };
+                let reserved = period_amount.checked_mul(count).ok_or(TokenError::Overflow)?;
+                let extension = period.checked_mul(count).ok_or(TokenError::Overflow)?;
+                // Same rate as before, so everything vested so far stays vested
+                self.end_time = self.end_time.checked_add(extension).ok_or(TokenError::Overflow)?;
+                self.amount = self.amount.checked_add(reserved).ok_or(TokenError::Overflow)?;
+                self.periods += count;
+                Ok(reserved)
+            }
+
+            /// Adds `amount` to a running stream. With `extend_end` the rate is kept and end_time moves
+            /// out, otherwise end_time is kept and the rate goes up. Amounts vested so far are unchanged.
+            pub fn top_up(&mut self, now: u64, amount: u64, extend_end: bool) -> ProgramResult {
+                self.assert_rate_adjustable(now)?;
+                // Linear part of the schedule runs from the cliff when there is one
+                let (base_time, base_amount) = if self.cliff_time != 0 {
+                    (self.cliff_time, self.cliff_amount)
+                } else {
+                    (self.start_time, 0)
+                };
+                if extend_end {
+                    let extension = (amount as u128)
+                        .checked_mul((self.end_time - base_time) as u128)
+                        .and_then(|amt| amt.checked_div(self.amount.checked_sub(base_amount)? as u128))
+                        .ok_or(TokenError::InvalidInstruction)?;
+                    let extension = u64::try_from(extension).map_err(|_| TokenError::Overflow)?;
+                    self.end_time = self.end_time.checked_add(extension).ok_or(TokenError::Overflow)?;
+                } else {
+                    self.rebase(now)?;
+                }
+                self.amount = self.amount.checked_add(amount).ok_or(TokenError::Overflow)?;
+                Ok(())
+            }
+
+            /// Stops vesting while the arbiter looks into a dispute
+            pub fn freeze(&mut self, now: u64) -> Result<(), ProgramError> {
+                if self.frozen {
+                    return Err(TokenError::StreamFrozen.into());
+                }
+                if now >= self.end_time {
+                    return Err(TokenError::TimeEnd.into());
+                }
+                if self.paused == 0 {
+                    self.paused = 1;
+                    self.paused_at = now;
+                }
+                self.frozen = true;
+                Ok(())
+            }
+            /// Ends the stream at `now`. With `keep_unvested` the unvested balance vests at once,
+            /// otherwise it leaves the stream. Returns the amount that left the stream.
+            pub fn end_now(&mut self, now: u64, keep_unvested: bool) -> Result<u64, ProgramError> {
+                let vested = self.vested_amount(now)?;
+                // Open-ended streams reserve nothing, so vested can run past the amount
+                let released = if keep_unvested { 0 } else { self.amount.saturating_sub(vested) };
+                self.amount = self.amount.checked_sub(released).ok_or(TokenError::Overflow)?;
+                self.start_time = self.start_time.min(now);
+                self.end_time = now;
+                self.cliff_time = 0;
+                self.cliff_amount = 0;
+                self.paused = 0;
+                self.paused_at = 0;
+                self.pause_window_start = 0;
+                self.pause_window_end = 0;
+                self.renewable = false;
+                Ok(released)
+            }
+            /// Ends an open-ended stream whose vault paid only `paid` of the `owed` amount. It becomes
+            /// a finished fixed stream of what was owed, returns the debt left to withdraw.
+            pub fn keep_rate_debt(&mut self, now: u64, owed: u64, paid: u64) -> Result<u64, ProgramError> {
+                let debt = owed.checked_sub(paid).ok_or(TokenError::Overflow)?;
+                self.amount = self.withdrawn.checked_add(owed).ok_or(TokenError::Overflow)?;
+                self.withdrawn = self.withdrawn.checked_add(paid).ok_or(TokenError::Overflow)?;
+                self.rate = 0;
+                self.end_now(now, true)?;
+                Ok(debt)
+            }
+            /// Ends a frozen stream for the arbiter, the unvested balance goes to the recipient
+            /// or back to the sender. Returns the amount released to the sender.
+            pub fn resolve(&mut self, now: u64, release_to_recipient: bool) -> Result<u64, ProgramError> {
+                if !self.frozen {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                let released = self.end_now(now, release_to_recipient)?;
+                self.frozen = false;
+                Ok(released)
+            }
+            /// Share of the vested amount beneficiary `index` can still withdraw. Shares are cut from
+            /// cumulative basis points so rounding never pays out more than has vested.
+            pub fn beneficiary_withdrawable(&self, index: usize, now: u64) -> Result<u64, ProgramError> {
+                let vested = self.vested_amount(now)? as u128;
+                let bps_before: u128 = self.beneficiaries[..index].iter().map(|b| b.share_bps as u128).sum();
+                let bps_after = bps_before + self.beneficiaries[index].share_bps as u128;
+                let share = (vested * bps_after / 10000 - vested * bps_before / 10000) as u64;
+                Ok(share.saturating_sub(self.beneficiaries[index].withdrawn))
+            }
+            /// Lowers the stream to `amount` without moving end_time, so the rate over the rest of the
+            /// stream drops. Returns the amount released back to the sender.
+            pub fn reduce_amount(&mut self, now: u64, amount: u64) -> Result<u64, ProgramError> {
+                self.assert_rate_adjustable(now)?;
+                self.rebase(now)?;
+                // Never below what has vested, nor below a cliff that is still to come
+                if amount < self.vested_amount(now)? || amount < self.cliff_amount || amount >= self.amount {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                let released = self.amount - amount;
+                self.amount = amount;
+                Ok(released)
+            }
+
+            fn assert_rate_adjustable(&self, now: u64) -> ProgramResult {
+                // Custom curves, open-ended and renewable streams have no single rate to change
+                if !self.breakpoints.is_empty() || self.rate != 0 || self.renewable {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                if self.paused == 1 || now < self.pause_window_end {
+                    return Err(TokenError::AlreadyPaused.into());
+                }
+                if now >= self.end_time {
+                    return Err(TokenError::TimeEnd.into());
+                }
+                Ok(())
+            }
+
+            /// Restarts the linear part of the schedule from what has vested at `now`
+            fn rebase(&mut self, now: u64) -> ProgramResult {
+                let base_time = if self.cliff_time != 0 { self.cliff_time } else { self.start_time };
+                let vested_at = match now.saturating_sub(self.start_time).checked_div(self.release_interval) {
+                    Some(periods) => self.start_time + periods * self.release_interval,
+                    None => now,
+                };
+                if vested_at > base_time {
+                    self.cliff_amount = self.vested_amount(now)?;
+                    self.cliff_time = vested_at;
+                }
+                Ok(())
+            }
+
+            /// Moves the whole schedule `time_spent` seconds later
+            fn shift_schedule(&mut self, time_spent: u64) -> ProgramResult {
+                self.start_time = self.start_time.checked_add(time_spent).ok_or(TokenError::Overflow)?;
+                // Open-ended streams keep their end_time at u64::MAX
+                self.end_time = if self.rate != 0 {
+                    self.end_time.saturating_add(time_spent)
+                } else {
+                    self.end_time.checked_add(time_spent).ok_or(TokenError::Overflow)?
+                };
+                if self.cliff_time != 0 {
+                    self.cliff_time = self.cliff_time.checked_add(time_spent).ok_or(TokenError::Overflow)?;
+                }
+                for point in self.breakpoints.iter_mut() {
+                    point.time = point.time.checked_add(time_spent).ok_or(TokenError::Overflow)?;
+                }
+                Ok(())
+            }
+
+            /// Records a pause from `pause_start` to `pause_end`. The schedule is moved out by the
+            /// window up front and `vested_amount` maps times before `pause_end` back onto it.
+            pub fn schedule_pause(&mut self, now: u64, pause_start: u64, pause_end: u64) -> ProgramResult {
+                if self.paused == 1 || now < self.pause_window_end {
+                    return Err(TokenError::AlreadyPaused.into());
+                }
+                // Forfeited time needs a resume to release the reservation
+                if self.pause_forfeits || pause_start <= now || pause_end <= pause_start {
+                    return Err(TokenError::InvalidInstruction.into());
+                }
+                if pause_start >= self.end_time {
+                    return Err(TokenError::TimeEnd.into());
+                }
+                // Every time of a fixed stream lies before end_time, so the window fits when end_time does
+                if self.rate == 0 && pause_end - pause_start > u64::MAX - self.end_time {
+                    return Err(TokenError::Overflow.into());
+                }
+                self.shift_schedule(pause_end - pause_start)?;
+                self.pause_window_start = pause_start;
+                self.pause_window_end = pause_end;
+                Ok(())
+            }
+        }
+        impl StreamSchedule for $stream {
+            fn vested_amount(&self, now: u64) -> Result<u64, ProgramError> {
+                // A paused stream stops vesting at the moment it was paused
+                let now = if self.paused == 1 { now.min(self.paused_at) } else { now };
+                // Schedule already runs later by the pause window, map earlier times back onto it
+                let now = if now < self.pause_window_end {
+                    now.min(self.pause_window_start) + (self.pause_window_end - self.pause_window_start)
+                } else {
+                    now
+                };
+                if now >= self.end_time {
+                    return Ok(self.amount);
+                }
+                if now < self.start_time {
+                    return Ok(0);
+                }
+                // Step-wise streams only release what was streamed up to the last completed interval
+                let now = match (now - self.start_time).checked_div(self.release_interval) {
+                    Some(periods) => self.start_time + periods * self.release_interval,
+                    None => now,
+                };
+                if self.rate != 0 {
+                    // Open-ended streams accrue until cancelled, the vault caps what is paid out
+                    return Ok((now - self.start_time).saturating_mul(self.rate));
+                }
+                if !self.breakpoints.is_empty() {
+                    return self.breakpoint_amt(now);
+                }
+                if self.cliff_time == 0 {
+                    return calculate_vested_amount(self.start_time, self.end_time, 0, self.amount, now);
+                }
+                if now < self.cliff_time {
+                    return Ok(0);
+                }
+                // Remainder after the cliff streams linearly from cliff_time to end_time
+                calculate_vested_amount(self.cliff_time, self.end_time, self.cliff_amount, self.amount, now)
+            }
+            fn withdrawn_amount(&self) -> u64 {
+                self.withdrawn
+            }
+            fn stream_amount(&self) -> u64 {
+                self.amount
+            }
+            fn pause(&mut self, now: u64) -> ProgramResult {
+                // Only one pause at a time, scheduled or not
+                if self.paused == 1 || now < self.pause_window_end {
+                    return Err(TokenError::AlreadyPaused.into());
+                }
+                self.withdraw_limit = self.vested_amount(now)?;
+                self.paused = 1;
+                self.paused_at = now;
+                Ok(())
+            }
+            fn resume(&mut self, now: u64) -> ProgramResult {
+                if self.paused == 0 || self.paused_at == 0 {
+                    return Err(TokenError::AlreadyResumed.into());
+                }
+                // Paused time is added to the end of the stream
+                let time_spent = now.checked_sub(self.paused_at).ok_or(TokenError::Overflow)?;
+                self.shift_schedule(time_spent)?;
+                self.paused = 0;
+                self.paused_at = 0;
+                Ok(())
+            }
+        }
+        impl_resume_forfeit!($stream);
+    };
+}
+/// Schedule of the multisig streams, linear between start_time and end_time
+macro_rules! impl_multisig_schedule {
+    ($stream:ty) => {
+        impl StreamSchedule for $stream {
+            fn vested_amount(&self, now: u64) -> Result<u64, ProgramError> {
+                // A paused stream stops vesting at the moment it was paused
+                let now = if self.paused == 1 { now.min(self.paused_at) } else { now };
+                calculate_vested_amount(self.start_time, self.end_time, 0, self.amount, now)
+            }
+            fn withdrawn_amount(&self) -> u64 {
+                self.withdrawn
+            }
+            fn stream_amount(&self) -> u64 {
+                self.amount
+            }
+            fn pause(&mut self, now: u64) -> ProgramResult {
+                if self.paused == 1 {
+                    return Err(TokenError::AlreadyPaused.into());
+                }
+                self.withdraw_limit = self.vested_amount(now)?;
+                self.paused = 1;
+                self.paused_at = now;
+                Ok(())
+            }
+            fn resume(&mut self, now: u64) -> ProgramResult {
+                if self.paused == 0 || self.paused_at == 0 {
+                    return Err(TokenError::AlreadyResumed.into());
+                }
+                // Paused time is added to the end of the stream
+                let time_spent = now.checked_sub(self.paused_at).ok_or(TokenError::Overflow)?;
+                self.start_time = self.start_time.checked_add(time_spent).ok_or(TokenError::Overflow)?;
+                self.end_time = self.end_time.checked_add(time_spent).ok_or(TokenError::Overflow)?;
+                self.paused = 0;
+                self.paused_at = 0;
+                Ok(())
+            }
+        }
+        impl_resume_forfeit!($stream);
+    };
+}
 #[repr(C)]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
 pub struct Escrow{
@@ -18,16 +644,10 @@ pub struct Escrow{
     pub sender:   Pubkey,
     pub recipient: Pubkey,
 }
-impl Escrow {
-    pub fn allowed_amt(&self, now: u64) -> u64 {
-        (
-        ((now - self.start_time) as f64) / ((self.end_time - self.start_time) as f64) * self.amount as f64
-        ) as u64 
-    }
-}
+impl_legacy_schedule!(Escrow);
 /// Initializeing solana stream states
 #[repr(C)]
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Default)]
 pub struct Stream{
     pub start_time: u64,
     pub end_time: u64,
@@ -37,15 +657,56 @@ pub struct Stream{
     pub sender:   Pubkey,
     pub recipient: Pubkey,
     pub withdrawn: u64,
-    pub paused_at: u64
-}
-impl Stream {
-    pub fn allowed_amt(&self, now: u64) -> u64 {
-        (
-        ((now - self.start_time) as f64) / ((self.end_time - self.start_time) as f64) * self.amount as f64
-        ) as u64
-    }
+    pub paused_at: u64,
+    /// Nothing is withdrawable before this time, 0 when the stream has no cliff
+    pub cliff_time: u64,
+    /// Amount unlocked at once when the cliff is reached
+    pub cliff_amount: u64,
+    /// Funds unlock in steps of this many seconds, 0 for per second streaming
+    pub release_interval: u64,
+    /// Custom vesting curve, empty for a linear stream
+    pub breakpoints: Vec<Breakpoint>,
+    /// Mint of the 1-of-1 token representing the recipient position, default when not tokenized
+    pub receivable_mint: Pubkey,
+    /// Paid to the keeper from the sender's free balance on each crank, 0 for no tip
+    pub crank_tip: u64,
+    /// Minimum seconds between two crank withdrawals, 0 for no limit
+    pub crank_interval: u64,
+    pub last_cranked: u64,
+    /// Starts a new period of the same length and amount after end_time
+    pub renewable: bool,
+    /// Number of periods the stream covers, amount and duration are multiples of one period
+    pub periods: u64,
+    /// Units streamed per second by an open-ended stream, 0 for a fixed amount and end_time
+    pub rate: u64,
+    /// Who can cancel the stream, one of the `CANCEL_BY_*` policies
+    pub cancel_policy: u8,
+    /// Who can pause the stream, one of the `PAUSE_*` policies
+    pub pause_policy: u8,
+    /// Paused time is forfeited instead of extending end_time
+    pub pause_forfeits: bool,
+    /// Start of the scheduled pause window, 0 when none was scheduled
+    pub pause_window_start: u64,
+    /// End of the scheduled pause window, the stream resumes on its own at this time
+    pub pause_window_end: u64,
+    /// Name, reference and memo describing the stream, editable until start_time
+    pub metadata: StreamMetadata,
+    /// Seeds of the stream address, default when the stream lives in a keypair account
+    pub seeds: StreamSeeds,
+    /// Account that paid the rent of the stream, default on streams paid for by the sender
+    pub rent_payer: Pubkey,
+    /// Accounts splitting the payouts by share, empty when the recipient receives everything
+    pub beneficiaries: Vec<Beneficiary>,
+    /// Withdrawals need the recipient's signature so it decides when it gets paid
+    pub recipient_must_sign: bool,
+    /// Third party that can freeze the stream and settle a dispute, default when there is none
+    pub arbiter: Pubkey,
+    /// Set by the arbiter, nothing moves until the dispute is resolved
+    pub frozen: bool,
 }
+impl_stream_schedule!(Stream);
+impl_stream_account!(Stream, StreamV0, KIND_SOL_STREAM, std::mem::size_of::<StreamV0>(),
+    [start_time, end_time, paused, withdraw_limit, amount, sender, recipient, withdrawn, paused_at]);
 #[repr(C)]
 #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
 pub struct TokenEscrow{
@@ -58,16 +719,10 @@ pub struct TokenEscrow{
     pub recipient: Pubkey,
     pub token_mint: Pubkey,
 }
-impl TokenEscrow {
-    pub fn allowed_amt(&self, now: u64) -> u64 {
-        (
-        ((now - self.start_time) as f64) / ((self.end_time - self.start_time) as f64) * self.amount as f64
-        ) as u64 
-    }
-}
+impl_legacy_schedule!(TokenEscrow);
 /// Initializeing token stream state
 #[repr(C)]
-#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
 pub struct StreamToken{
     pub start_time: u64,
     pub end_time: u64,
@@ -79,14 +734,102 @@ pub struct StreamToken{
     pub token_mint: Pubkey,
     pub withdrawn: u64,
     pub paused_at: u64,
+    /// Nothing is withdrawable before this time, 0 when the stream has no cliff
+    pub cliff_time: u64,
+    /// Amount unlocked at once when the cliff is reached
+    pub cliff_amount: u64,
+    /// Funds unlock in steps of this many seconds, 0 for per second streaming
+    pub release_interval: u64,
+    /// Custom vesting curve, empty for a linear stream
+    pub breakpoints: Vec<Breakpoint>,
+    /// Mint of the 1-of-1 token representing the recipient position, default when not tokenized
+    pub receivable_mint: Pubkey,
+    /// Paid to the keeper from the sender's free balance on each crank, 0 for no tip
+    pub crank_tip: u64,
+    /// Minimum seconds between two crank withdrawals, 0 for no limit
+    pub crank_interval: u64,
+    pub last_cranked: u64,
+    /// Starts a new period of the same length and amount after end_time
+    pub renewable: bool,
+    /// Number of periods the stream covers, amount and duration are multiples of one period
+    pub periods: u64,
+    /// Units streamed per second by an open-ended stream, 0 for a fixed amount and end_time
+    pub rate: u64,
+    /// Who can cancel the stream, one of the `CANCEL_BY_*` policies
+    pub cancel_policy: u8,
+    /// Who can pause the stream, one of the `PAUSE_*` policies
+    pub pause_policy: u8,
+    /// Paused time is forfeited instead of extending end_time
+    pub pause_forfeits: bool,
+    /// Start of the scheduled pause window, 0 when none was scheduled
+    pub pause_window_start: u64,
+    /// End of the scheduled pause window, the stream resumes on its own at this time
+    pub pause_window_end: u64,
+    /// Name, reference and memo describing the stream, editable until start_time
+    pub metadata: StreamMetadata,
+    /// Seeds of the stream address, default when the stream lives in a keypair account
+    pub seeds: StreamSeeds,
+    /// Account that paid the rent of the stream, default on streams paid for by the sender
+    pub rent_payer: Pubkey,
+    /// Accounts splitting the payouts by share, empty when the recipient receives everything
+    pub beneficiaries: Vec<Beneficiary>,
+    /// Withdrawals need the recipient's signature so it decides when it gets paid
+    pub recipient_must_sign: bool,
+    /// Third party that can freeze the stream and settle a dispute, default when there is none
+    pub arbiter: Pubkey,
+    /// Set by the arbiter, nothing moves until the dispute is resolved
+    pub frozen: bool,
 }
-impl StreamToken {
-    pub fn allowed_amt(&self, now: u64) -> u64 {
-        (
-        ((now - self.start_time) as f64) / ((self.end_time - self.start_time) as f64) * self.amount as f64
-        ) as u64 
+impl_stream_schedule!(StreamToken);
+impl_stream_account!(StreamToken, StreamTokenV0, KIND_TOKEN_STREAM, std::mem::size_of::<StreamTokenV0>(),
+    [start_time, end_time, paused, withdraw_limit, amount, sender, recipient, token_mint, withdrawn, paused_at]);
+/// Parties and nonce a stream address was derived from. They are kept as created,
+/// so the address can still be checked after the sender or recipient changes.
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamSeeds{
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub nonce: u64,
+    pub bump: u8,
+}
+/// Descriptive fields of a stream, each zero padded UTF-8
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct StreamMetadata{
+    pub name: [u8; 32],
+    /// External reference such as an invoice number
+    pub reference: [u8; 32],
+    pub memo: [u8; 64],
+    /// Optional link to off-chain details, zeroed when unused
+    pub uri: [u8; 128],
+}
+impl Default for StreamMetadata {
+    fn default() -> Self {
+        StreamMetadata {
+            name: [0; 32],
+            reference: [0; 32],
+            memo: [0; 64],
+            uri: [0; 128],
+        }
     }
 }
+/// Account receiving a fixed share of a split stream
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Beneficiary{
+    pub address: Pubkey,
+    /// Share of the vested amount in basis points, the shares of a stream add up to 10000
+    pub share_bps: u16,
+    pub withdrawn: u64,
+}
+/// Point on a custom vesting curve, amount is cumulative
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Breakpoint{
+    pub time: u64,
+    pub amount: u64,
+}
 #[repr(C)]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
 pub struct Withdraw{
@@ -114,6 +857,11 @@ pub struct WhiteList{
     pub counter:u8,
 }
 
+impl WhiteList {
+    /// Serialized length of one entry
+    pub const LEN: usize = 33;
+}
+
 impl Multisig {
     pub fn from_account(account:&AccountInfo)-> Result<Multisig, ProgramError> {
             let md: Multisig =try_from_slice_unchecked(&account.data.borrow_mut())?;
@@ -135,9 +883,10 @@ pub struct EscrowMultisig{
     pub multisig_safe: Pubkey,
     pub can_cancel: bool,
 }
+impl_legacy_schedule!(EscrowMultisig);
 /// Initializeing solana stream states
 #[repr(C)]
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Default)]
 pub struct StreamMultisig{
     pub start_time: u64,
     pub end_time: u64,
@@ -151,7 +900,20 @@ pub struct StreamMultisig{
     pub can_cancel: bool,
     pub withdrawn: u64,
     pub paused_at: u64,
+    /// Mint of the 1-of-1 token representing the recipient position, default when not tokenized
+    pub receivable_mint: Pubkey,
+    /// Who can pause the stream, one of the `PAUSE_*` policies
+    pub pause_policy: u8,
+    /// Paused time is forfeited instead of extending end_time
+    pub pause_forfeits: bool,
+    /// Name, reference and memo describing the stream, editable until start_time
+    pub metadata: StreamMetadata,
+    /// Account that paid the rent of the stream, default on streams paid for by the sender
+    pub rent_payer: Pubkey,
 }
+impl_multisig_schedule!(StreamMultisig);
+impl_multisig_account!(StreamMultisig, StreamMultisigV0, KIND_SOL_MULTISIG_STREAM,
+    [start_time, end_time, paused, withdraw_limit, amount, sender, recipient, signed_by, multisig_safe, can_cancel, withdrawn, paused_at]);
 #[repr(C)]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
 pub struct TokenEscrowMultisig{
@@ -167,8 +929,9 @@ pub struct TokenEscrowMultisig{
     pub multisig_safe: Pubkey,
     pub can_cancel: bool,
 }
+impl_legacy_schedule!(TokenEscrowMultisig);
 #[repr(C)]
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Default)]
 pub struct TokenStreamMultisig{
     pub start_time: u64,
     pub end_time: u64,
@@ -183,54 +946,95 @@ pub struct TokenStreamMultisig{
     pub can_cancel: bool,
     pub withdrawn: u64,
     pub paused_at: u64,
+    /// Mint of the 1-of-1 token representing the recipient position, default when not tokenized
+    pub receivable_mint: Pubkey,
+    /// Who can pause the stream, one of the `PAUSE_*` policies
+    pub pause_policy: u8,
+    /// Paused time is forfeited instead of extending end_time
+    pub pause_forfeits: bool,
+    /// Name, reference and memo describing the stream, editable until start_time
+    pub metadata: StreamMetadata,
+    /// Account that paid the rent of the stream, default on streams paid for by the sender
+    pub rent_payer: Pubkey,
 }
+impl_multisig_schedule!(TokenStreamMultisig);
+impl_multisig_account!(TokenStreamMultisig, TokenStreamMultisigV0, KIND_TOKEN_MULTISIG_STREAM,
+    [start_time, end_time, paused, withdraw_limit, amount, sender, recipient, token_mint, signed_by, multisig_safe, can_cancel, withdrawn, paused_at]);
 impl TokenEscrowMultisig {
     pub fn from_account(account:&AccountInfo)-> Result<TokenEscrowMultisig, ProgramError> {
         let md: TokenEscrowMultisig =try_from_slice_unchecked(&account.data.borrow_mut())?;
         Ok(md)
     }
-
-    pub fn allowed_amt(&self, now: u64) -> u64 {
-        (
-        ((now - self.start_time) as f64) / ((self.end_time - self.start_time) as f64) * self.amount as f64
-        ) as u64 
-    }
-}
-impl StreamMultisig {
-    pub fn from_account(account:&AccountInfo)-> Result<StreamMultisig, ProgramError> {
-        let md: StreamMultisig =try_from_slice_unchecked(&account.data.borrow_mut())?;
-        Ok(md)
-    }
-
-    pub fn allowed_amt(&self, now: u64) -> u64 {
-        (
-        ((now - self.start_time) as f64) / ((self.end_time - self.start_time) as f64) * self.amount as f64
-        ) as u64 
-    }
-}
-impl TokenStreamMultisig {
-    pub fn from_account(account:&AccountInfo)-> Result<TokenStreamMultisig, ProgramError> {
-        let md: TokenStreamMultisig =try_from_slice_unchecked(&account.data.borrow_mut())?;
-        Ok(md)
-    }
-
-    pub fn allowed_amt(&self, now: u64) -> u64 {
-        (
-        ((now - self.start_time) as f64) / ((self.end_time - self.start_time) as f64) * self.amount as f64
-        ) as u64 
-    }
 }
 impl EscrowMultisig {
     pub fn from_account(account:&AccountInfo)-> Result<EscrowMultisig, ProgramError> {
         let md: EscrowMultisig =try_from_slice_unchecked(&account.data.borrow_mut())?;
         Ok(md)
     }
-
-    pub fn allowed_amt(&self, now: u64) -> u64 {
-        (
-        ((now - self.start_time) as f64) / ((self.end_time - self.start_time) as f64) * self.amount as f64
-        ) as u64 
-    }
+}
+/// Layout of `Stream` accounts created before the version header
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct StreamV0{
+    pub start_time: u64,
+    pub end_time: u64,
+    pub paused: u64,
+    pub withdraw_limit: u64,
+    pub amount: u64,
+    pub sender:   Pubkey,
+    pub recipient: Pubkey,
+    pub withdrawn: u64,
+    pub paused_at: u64,
+}
+/// Layout of `StreamToken` accounts created before the version header
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct StreamTokenV0{
+    pub start_time: u64,
+    pub end_time: u64,
+    pub paused: u64,
+    pub withdraw_limit: u64,
+    pub amount: u64,
+    pub sender:   Pubkey,
+    pub recipient: Pubkey,
+    pub token_mint: Pubkey,
+    pub withdrawn: u64,
+    pub paused_at: u64,
+}
+/// Layout of `StreamMultisig` accounts created before the version header
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct StreamMultisigV0{
+    pub start_time: u64,
+    pub end_time: u64,
+    pub paused: u64,
+    pub withdraw_limit: u64,
+    pub amount: u64,
+    pub sender:   Pubkey,
+    pub recipient: Pubkey,
+    pub signed_by: Vec<WhiteList>,
+    pub multisig_safe: Pubkey,
+    pub can_cancel: bool,
+    pub withdrawn: u64,
+    pub paused_at: u64,
+}
+/// Layout of `TokenStreamMultisig` accounts created before the version header
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TokenStreamMultisigV0{
+    pub start_time: u64,
+    pub end_time: u64,
+    pub paused: u64,
+    pub withdraw_limit: u64,
+    pub amount: u64,
+    pub sender:   Pubkey,
+    pub recipient: Pubkey,
+    pub token_mint: Pubkey,
+    pub signed_by: Vec<WhiteList>,
+    pub multisig_safe: Pubkey,
+    pub can_cancel: bool,
+    pub withdrawn: u64,
+    pub paused_at: u64,
 }
 #[repr(C)]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
@@ -263,3 +1067,237 @@ impl TokenTransfer {
         Ok(md)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear(amount: u64, start_time: u64, end_time: u64) -> Stream {
+        Stream {
+            start_time,
+            end_time,
+            amount,
+            sender: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            periods: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn linear_stream_vests_between_start_and_end() {
+        let stream = linear(1000, 100, 200);
+        assert_eq!(stream.vested_amount(100).unwrap(), 0);
+        assert_eq!(stream.vested_amount(150).unwrap(), 500);
+        assert_eq!(stream.vested_amount(250).unwrap(), 1000);
+    }
+
+    #[test]
+    fn cliff_unlocks_at_its_edge() {
+        let mut stream = linear(1000, 100, 200);
+        stream.cliff_time = 150;
+        stream.cliff_amount = 300;
+        assert_eq!(stream.vested_amount(149).unwrap(), 0);
+        assert_eq!(stream.vested_amount(150).unwrap(), 300);
+        assert_eq!(stream.vested_amount(175).unwrap(), 650);
+    }
+
+    #[test]
+    fn release_interval_rounds_down_to_completed_intervals() {
+        let mut stream = linear(1000, 0, 100);
+        stream.release_interval = 30;
+        assert_eq!(stream.vested_amount(29).unwrap(), 0);
+        assert_eq!(stream.vested_amount(30).unwrap(), 300);
+        assert_eq!(stream.vested_amount(89).unwrap(), 600);
+        // Last interval is cut short by end_time and releases the rest there
+        assert_eq!(stream.vested_amount(99).unwrap(), 900);
+        assert_eq!(stream.vested_amount(100).unwrap(), 1000);
+    }
+
+    #[test]
+    fn breakpoints_interpolate_each_segment() {
+        let mut stream = linear(1000, 100, 200);
+        stream.breakpoints = vec![Breakpoint { time: 150, amount: 800 }];
+        assert_eq!(stream.breakpoint_amt(125).unwrap(), 400);
+        assert_eq!(stream.breakpoint_amt(150).unwrap(), 800);
+        assert_eq!(stream.breakpoint_amt(175).unwrap(), 900);
+        assert_eq!(stream.vested_amount(175).unwrap(), 900);
+    }
+
+    #[test]
+    fn renew_adds_the_periods_the_sender_can_fund() {
+        let mut stream = linear(1000, 0, 100);
+        stream.renewable = true;
+        assert_eq!(stream.renew(99, 5000).unwrap(), 0);
+        assert_eq!(stream.renew(250, 1500).unwrap(), 1000);
+        assert_eq!((stream.end_time, stream.amount, stream.periods), (200, 2000, 2));
+        assert_eq!(stream.renew(250, 5000).unwrap(), 1000);
+        assert_eq!((stream.end_time, stream.amount, stream.periods), (300, 3000, 3));
+        assert_eq!(stream.vested_amount(250).unwrap(), 2500);
+    }
+
+    #[test]
+    fn top_up_keeps_what_has_vested() {
+        let mut stream = linear(1000, 0, 100);
+        stream.top_up(50, 500, true).unwrap();
+        assert_eq!((stream.end_time, stream.amount), (150, 1500));
+        assert_eq!(stream.vested_amount(50).unwrap(), 500);
+
+        let mut stream = linear(1000, 0, 100);
+        stream.top_up(50, 500, false).unwrap();
+        assert_eq!((stream.end_time, stream.amount), (100, 1500));
+        assert_eq!(stream.vested_amount(50).unwrap(), 500);
+        assert_eq!(stream.vested_amount(75).unwrap(), 1000);
+    }
+
+    #[test]
+    fn reduce_amount_never_goes_below_vested() {
+        let mut stream = linear(1000, 0, 100);
+        assert!(stream.clone().reduce_amount(50, 400).is_err());
+        assert!(stream.clone().reduce_amount(50, 1000).is_err());
+        assert_eq!(stream.reduce_amount(50, 800).unwrap(), 200);
+        assert_eq!(stream.vested_amount(50).unwrap(), 500);
+        assert_eq!(stream.vested_amount(100).unwrap(), 800);
+    }
+
+    #[test]
+    fn resume_forfeit_keeps_the_rate_over_the_rest() {
+        let mut stream = linear(1000, 0, 100);
+        stream.pause(40).unwrap();
+        assert_eq!(stream.vested_amount(60).unwrap(), 400);
+        assert_eq!(stream.resume_forfeit(60).unwrap(), 200);
+        assert_eq!((stream.start_time, stream.end_time, stream.amount), (20, 100, 800));
+        assert_eq!(stream.vested_amount(60).unwrap(), 400);
+        assert_eq!(stream.vested_amount(80).unwrap(), 600);
+    }
+
+    #[test]
+    fn scheduled_pause_stops_vesting_inside_the_window() {
+        let mut stream = linear(1000, 100, 200);
+        stream.schedule_pause(110, 120, 140).unwrap();
+        assert_eq!((stream.start_time, stream.end_time), (120, 220));
+        assert_eq!(stream.vested_amount(115).unwrap(), 150);
+        assert_eq!(stream.vested_amount(120).unwrap(), 200);
+        assert_eq!(stream.vested_amount(139).unwrap(), 200);
+        assert_eq!(stream.vested_amount(150).unwrap(), 300);
+        assert_eq!(stream.vested_amount(220).unwrap(), 1000);
+        assert!(stream.clone().schedule_pause(130, 150, 160).is_err());
+        assert!(stream.clone().pause(130).is_err());
+    }
+
+    #[test]
+    fn scheduled_pause_rejects_bad_windows() {
+        let stream = linear(1000, 100, 200);
+        assert!(stream.clone().schedule_pause(120, 120, 140).is_err());
+        assert!(stream.clone().schedule_pause(110, 140, 140).is_err());
+        assert_eq!(stream.clone().schedule_pause(110, 200, 210), Err(TokenError::TimeEnd.into()));
+        // Window that would move the schedule past u64::MAX
+        assert_eq!(stream.clone().schedule_pause(50, 60, u64::MAX), Err(TokenError::Overflow.into()));
+        assert_eq!(stream.clone().schedule_pause(50, 60, u64::MAX - 139), Err(TokenError::Overflow.into()));
+        assert!(stream.clone().schedule_pause(50, 60, u64::MAX - 140).is_ok());
+        let mut forfeiting = stream;
+        forfeiting.pause_forfeits = true;
+        assert!(forfeiting.schedule_pause(110, 120, 140).is_err());
+    }
+
+    #[test]
+    fn beneficiary_shares_never_add_up_past_vested() {
+        let mut stream = linear(100, 0, 100);
+        stream.beneficiaries = [3333, 3333, 3334].iter()
+            .map(|&share_bps| Beneficiary { address: Pubkey::new_unique(), share_bps, withdrawn: 0 })
+            .collect();
+        for now in [1, 7, 50, 99, 100] {
+            let vested = stream.vested_amount(now).unwrap();
+            let shares: u64 = (0..3).map(|i| stream.beneficiary_withdrawable(i, now).unwrap()).sum();
+            assert_eq!(shares, vested);
+        }
+        stream.beneficiaries[0].withdrawn = 33;
+        assert_eq!(stream.beneficiary_withdrawable(0, 100).unwrap(), 0);
+    }
+
+    #[test]
+    fn end_now_handles_open_ended_streams() {
+        let mut stream = linear(0, 0, u64::MAX);
+        stream.rate = 10;
+        assert_eq!(stream.end_now(50, false).unwrap(), 0);
+        assert_eq!((stream.amount, stream.end_time), (0, 50));
+    }
+
+    #[test]
+    fn rate_debt_stays_withdrawable() {
+        let mut stream = linear(0, 0, u64::MAX);
+        stream.rate = 10;
+        stream.withdrawn = 100;
+        assert_eq!(stream.keep_rate_debt(50, 400, 150).unwrap(), 250);
+        assert_eq!((stream.rate, stream.amount, stream.withdrawn, stream.end_time), (0, 500, 250, 50));
+        assert_eq!(stream.withdrawable_amount(60).unwrap(), 250);
+    }
+
+    #[test]
+    fn terms_reject_self_streams_and_empty_streams() {
+        let stream = linear(1000, 100, 200);
+        let (sender, recipient) = (stream.sender, stream.recipient);
+        assert!(stream.assert_valid_terms(&sender, &recipient, 50).is_ok());
+        assert!(stream.assert_valid_terms(&sender, &sender, 50).is_err());
+        assert!(linear(0, 100, 200).assert_valid_terms(&sender, &recipient, 50).is_err());
+    }
+
+    #[test]
+    fn keeper_tip_stays_below_one_crank_interval() {
+        let mut stream = linear(1000, 100, 200);
+        let (sender, recipient) = (stream.sender, stream.recipient);
+        stream.crank_interval = 10;
+        stream.crank_tip = 99;
+        assert!(stream.assert_valid_terms(&sender, &recipient, 50).is_ok());
+        stream.crank_tip = 100;
+        assert!(stream.assert_valid_terms(&sender, &recipient, 50).is_err());
+        stream.crank_tip = 1;
+        stream.crank_interval = 0;
+        assert!(stream.assert_valid_terms(&sender, &recipient, 50).is_err());
+    }
+
+    #[test]
+    fn versioned_stream_round_trips() {
+        let mut stream = linear(1000, 100, 200);
+        stream.breakpoints = vec![Breakpoint { time: 150, amount: 800 }];
+        let mut data = vec![0; stream.account_len().unwrap()];
+        let (key, owner, mut lamports) = (Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        stream.init(&account).unwrap();
+        assert!(Stream::is_kind(&account));
+        assert!(!StreamToken::is_kind(&account));
+        assert_eq!(Stream::load(&account).unwrap(), stream);
+        stream.withdrawn = 500;
+        stream.save(&account).unwrap();
+        assert_eq!(Stream::load(&account).unwrap(), stream);
+    }
+
+    #[test]
+    fn legacy_stream_stays_in_its_layout() {
+        let legacy = StreamV0 {
+            start_time: 100,
+            end_time: 200,
+            paused: 0,
+            withdraw_limit: 0,
+            amount: 1000,
+            sender: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            withdrawn: 0,
+            paused_at: 0,
+        };
+        let mut data = legacy.try_to_vec().unwrap();
+        assert_eq!(data.len(), Stream::LEGACY_LEN);
+        let (key, owner, mut lamports) = (Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        assert!(Stream::is_kind(&account));
+        let mut stream = Stream::load(&account).unwrap();
+        assert_eq!((stream.amount, stream.sender, stream.periods), (1000, legacy.sender, 0));
+        stream.withdrawn = 400;
+        stream.save(&account).unwrap();
+        assert!(stream_header(&account.data.borrow()).is_none());
+        assert_eq!(Stream::load(&account).unwrap(), stream);
+        // Fields the legacy layout cannot hold are refused instead of dropped
+        stream.release_interval = 10;
+        assert_eq!(stream.save(&account), Err(TokenError::LegacyLayout.into()));
+    }
+}
@@ -1,13 +1,17 @@
 use solana_program::{
     pubkey::Pubkey,
-    account_info::{AccountInfo},
+    account_info::{AccountInfo,next_account_info},
     system_instruction,
     program::{invoke_signed,invoke},
     entrypoint::ProgramResult,
     program_error::ProgramError,
-    program_pack::Pack
+    program_pack::Pack,
+    sysvar::{rent::Rent,Sysvar},
 };
+use spl_token::instruction::AuthorityType;
 use super::error::TokenError;
+use super::state::{Beneficiary,Breakpoint,StreamMetadata,StreamSeeds};
+use super::{MAX_BENEFICIARIES,MAX_BREAKPOINTS,PREFIX_RECEIVABLE,PREFIX_STREAM,CANCEL_BY_SENDER,CANCEL_BY_RECIPIENT,CANCEL_BY_BOTH,PAUSE_BY_BOTH,PAUSE_BY_SENDER,PAUSE_NONE};
 use arrayref::array_ref;
 
 pub fn get_master_address_and_bump_seed(
@@ -29,6 +33,95 @@ pub fn assert_keys_equal(key1: Pubkey, key2: Pubkey) -> ProgramResult {
         Ok(())
     }
 }
+/// Amount vested at `now` on the segment from (start_time, start_amount) to (end_time, end_amount).
+/// Uses exact integer math and rounds down, so the recipient never receives more than has
+/// streamed and any remainder is released at end_time.
+pub fn calculate_vested_amount(
+    start_time: u64,
+    end_time: u64,
+    start_amount: u64,
+    end_amount: u64,
+    now: u64,
+) -> Result<u64, ProgramError> {
+    if now <= start_time {
+        return Ok(start_amount);
+    }
+    if now >= end_time {
+        return Ok(end_amount);
+    }
+    let remaining = end_amount.checked_sub(start_amount).ok_or(TokenError::Overflow)?;
+    let streamed = (remaining as u128)
+        .checked_mul((now - start_time) as u128)
+        .ok_or(TokenError::Overflow)?
+        .checked_div((end_time - start_time) as u128)
+        .ok_or(TokenError::Overflow)?;
+    let streamed = u64::try_from(streamed).map_err(|_| TokenError::Overflow)?;
+    start_amount.checked_add(streamed).ok_or_else(|| TokenError::Overflow.into())
+}
+/// Breakpoints must describe a non-decreasing vesting curve between start_time and end_time
+pub fn assert_valid_breakpoints(
+    breakpoints: &[Breakpoint],
+    start_time: u64,
+    end_time: u64,
+    amount: u64,
+) -> ProgramResult {
+    if breakpoints.len() > MAX_BREAKPOINTS {
+        return Err(TokenError::InvalidInstruction.into());
+    }
+    let mut prev_time = start_time;
+    let mut prev_amount = 0;
+    for point in breakpoints.iter() {
+        if point.time < prev_time || point.time > end_time || point.amount < prev_amount || point.amount > amount {
+            return Err(TokenError::InvalidInstruction.into());
+        }
+        prev_time = point.time;
+        prev_amount = point.amount;
+    }
+    Ok(())
+}
+/// Beneficiaries must be distinct, start with nothing withdrawn and share exactly 10000 basis points
+pub fn assert_valid_beneficiaries(beneficiaries: &[Beneficiary]) -> ProgramResult {
+    if beneficiaries.is_empty() {
+        return Ok(());
+    }
+    if beneficiaries.len() > MAX_BENEFICIARIES {
+        return Err(TokenError::InvalidInstruction.into());
+    }
+    let mut total_bps: u32 = 0;
+    for (i, beneficiary) in beneficiaries.iter().enumerate() {
+        if beneficiary.share_bps == 0 || beneficiary.withdrawn != 0 || beneficiary.address == Pubkey::default() {
+            return Err(TokenError::InvalidInstruction.into());
+        }
+        if beneficiaries[..i].iter().any(|b| b.address == beneficiary.address) {
+            return Err(TokenError::InvalidInstruction.into());
+        }
+        total_bps += beneficiary.share_bps as u32;
+    }
+    if total_bps != 10000 {
+        return Err(TokenError::InvalidInstruction.into());
+    }
+    Ok(())
+}
+/// Pause policy must be one of the `PAUSE_*` policies, forfeiting paused time only works on linear streams
+pub fn assert_valid_pause_terms(pause_policy: u8, pause_forfeits: bool, linear: bool) -> ProgramResult {
+    if pause_policy > PAUSE_NONE {
+        return Err(TokenError::InvalidInstruction.into());
+    }
+    if pause_forfeits && !linear {
+        return Err(TokenError::InvalidInstruction.into());
+    }
+    Ok(())
+}
+/// Every metadata field must be UTF-8 followed only by zero padding
+pub fn assert_valid_metadata(metadata: &StreamMetadata) -> ProgramResult {
+    for field in [&metadata.name[..], &metadata.reference[..], &metadata.memo[..], &metadata.uri[..]] {
+        let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+        if std::str::from_utf8(&field[..len]).is_err() || field[len..].iter().any(|b| *b != 0) {
+            return Err(TokenError::InvalidInstruction.into());
+        }
+    }
+    Ok(())
+}
 pub fn create_pda_account<'a>(
     payer: &AccountInfo<'a>,
     amount: u64,
@@ -142,6 +235,296 @@ pub fn get_multisig_data_and_bump_seed(
         program_id,
     )
 }
+pub fn get_receivable_mint_and_bump_seed(
+    prefix: &str,
+    stream: &Pubkey,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            prefix.as_bytes(),
+            &stream.to_bytes(),
+        ],
+        program_id,
+    )
+}
+/// Address of a stream account derived from its parties, `mint` is default for solana streams
+pub fn get_stream_address_and_bump_seed(
+    sender: &Pubkey,
+    recipient: &Pubkey,
+    mint: &Pubkey,
+    nonce: u64,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            PREFIX_STREAM.as_bytes(),
+            &sender.to_bytes(),
+            &recipient.to_bytes(),
+            &mint.to_bytes(),
+            &nonce.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+/// Checks that `stream` is the address derived from `seeds`, keypair streams have no seeds.
+/// Multisig streams carry no seeds and are always keypair accounts, so their handlers do not call this.
+pub fn assert_stream_address(
+    stream: &Pubkey,
+    mint: &Pubkey,
+    seeds: &StreamSeeds,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if seeds.sender == Pubkey::default() {
+        return Ok(());
+    }
+    let stream_address = Pubkey::create_program_address(
+        &[
+            PREFIX_STREAM.as_bytes(),
+            &seeds.sender.to_bytes(),
+            &seeds.recipient.to_bytes(),
+            &mint.to_bytes(),
+            &seeds.nonce.to_le_bytes(),
+            &[seeds.bump],
+        ],
+        program_id,
+    ).map_err(|_| TokenError::PublicKeyMismatch)?;
+    assert_keys_equal(stream_address, *stream)
+}
+/// Creates the data account of a stream, at the address derived from `seeds` when they are set
+/// and at a keypair address otherwise. Returns the seeds with the bump of the derived address.
+pub fn create_stream_account<'a>(
+    payer: &AccountInfo<'a>,
+    mint: &Pubkey,
+    mut seeds: StreamSeeds,
+    space: usize,
+    program_id: &Pubkey,
+    system_program: &AccountInfo<'a>,
+    stream: &AccountInfo<'a>,
+) -> Result<StreamSeeds, ProgramError> {
+    let amount = Rent::get()?.minimum_balance(space);
+    if seeds.sender == Pubkey::default() {
+        create_pda_account(payer, amount, space, program_id, system_program, stream)?;
+        return Ok(seeds);
+    }
+    let (stream_address, stream_bump) = get_stream_address_and_bump_seed(
+        &seeds.sender,
+        &seeds.recipient,
+        mint,
+        seeds.nonce,
+        program_id,
+    );
+    assert_keys_equal(stream_address, *stream.key)?;
+    seeds.bump = stream_bump;
+    let stream_signer_seeds: &[&[_]] = &[
+        PREFIX_STREAM.as_bytes(),
+        &seeds.sender.to_bytes(),
+        &seeds.recipient.to_bytes(),
+        &mint.to_bytes(),
+        &seeds.nonce.to_le_bytes(),
+        &[stream_bump],
+    ];
+    create_pda_account_signed(payer, amount, space, program_id, system_program, stream, stream_signer_seeds)?;
+    Ok(seeds)
+}
+/// Creates the 1-of-1 mint representing the recipient position of `stream` and mints
+/// its only token to `holder`. Reads the receivable mint, holder associated token account,
+/// token program, associated token program and rent accounts from `account_info_iter`.
+pub fn create_receivable_mint<'a, 'b>(
+    program_id: &Pubkey,
+    stream: &Pubkey,
+    payer: &AccountInfo<'b>,
+    holder: &AccountInfo<'b>,
+    system_program: &AccountInfo<'b>,
+    account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+) -> Result<Pubkey, ProgramError> {
+    let mint_info = next_account_info(account_info_iter)?; // receivable mint
+    let holder_associated_info = next_account_info(account_info_iter)?; // Associated token of holder for the receivable mint
+    let token_program_info = next_account_info(account_info_iter)?; // TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA
+    let associated_token_info = next_account_info(account_info_iter)?; // Associated token master {ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL}
+    let rent_info = next_account_info(account_info_iter)?; // rent address
+    if token_program_info.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (mint_address, bump_seed) = get_receivable_mint_and_bump_seed(
+        PREFIX_RECEIVABLE,
+        stream,
+        program_id,
+    );
+    assert_keys_equal(mint_address, *mint_info.key)?;
+    let mint_signer_seeds: &[&[_]] = &[
+        PREFIX_RECEIVABLE.as_bytes(),
+        &stream.to_bytes(),
+        &[bump_seed],
+    ];
+    let rent = Rent::get()?;
+    create_pda_account_signed(
+        payer,
+        rent.minimum_balance(spl_token::state::Mint::LEN),
+        spl_token::state::Mint::LEN,
+        &spl_token::id(),
+        system_program,
+        mint_info,
+        mint_signer_seeds
+    )?;
+    invoke(
+        &spl_token::instruction::initialize_mint(
+            token_program_info.key,
+            mint_info.key,
+            mint_info.key,
+            None,
+            0
+        )?,
+        &[
+            mint_info.clone(),
+            rent_info.clone(),
+            token_program_info.clone()
+        ],
+    )?;
+    invoke(
+        &spl_associated_token_account::create_associated_token_account(
+            payer.key,
+            holder.key,
+            mint_info.key,
+        ),&[
+            payer.clone(),
+            holder_associated_info.clone(),
+            holder.clone(),
+            mint_info.clone(),
+            token_program_info.clone(),
+            rent_info.clone(),
+            associated_token_info.clone(),
+            system_program.clone()
+        ]
+    )?;
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            token_program_info.key,
+            mint_info.key,
+            holder_associated_info.key,
+            mint_info.key,
+            &[],
+            1
+        )?,
+        &[
+            mint_info.clone(),
+            holder_associated_info.clone(),
+            token_program_info.clone()
+        ],
+        &[mint_signer_seeds],
+    )?;
+    // Dropping the mint authority keeps the supply at exactly one
+    invoke_signed(
+        &spl_token::instruction::set_authority(
+            token_program_info.key,
+            mint_info.key,
+            None,
+            AuthorityType::MintTokens,
+            mint_info.key,
+            &[]
+        )?,
+        &[
+            mint_info.clone(),
+            token_program_info.clone()
+        ],
+        &[mint_signer_seeds],
+    )?;
+    Ok(mint_address)
+}
+/// Checks that `holder` owns the token account holding the receivable token of a stream
+pub fn assert_receivable_holder(
+    holder: &Pubkey,
+    holder_token_account: &AccountInfo,
+    receivable_mint: &Pubkey,
+) -> ProgramResult {
+    if *holder_token_account.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let token_account = spl_token::state::Account::unpack(&holder_token_account.data.borrow())?;
+    if token_account.mint != *receivable_mint || token_account.owner != *holder || token_account.amount != 1 {
+        return Err(TokenError::EscrowMismatch.into());
+    }
+    Ok(())
+}
+/// Checks that a stream pays `payee`. Tokenized streams pay whoever holds the receivable token,
+/// whose token account is read from `account_info_iter`, other streams pay their recipient.
+pub fn assert_stream_payee<'a, 'b>(
+    payee: &Pubkey,
+    recipient: &Pubkey,
+    receivable_mint: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+) -> ProgramResult {
+    if *receivable_mint != Pubkey::default() {
+        let receivable_holder_info = next_account_info(account_info_iter)?; // holder token account of the receivable mint
+        return assert_receivable_holder(payee, receivable_holder_info, receivable_mint);
+    }
+    if payee != recipient {
+        return Err(TokenError::EscrowMismatch.into());
+    }
+    Ok(())
+}
+/// Account the rent of a stream goes back to. Streams created before rent_payer was
+/// recorded were paid for by the sender.
+pub fn rent_refund_address(rent_payer: &Pubkey, sender: &Pubkey) -> Pubkey {
+    if *rent_payer == Pubkey::default() { *sender } else { *rent_payer }
+}
+/// Checks that `token_account` is a token account of `mint`
+pub fn assert_token_account_mint(token_account: &AccountInfo, mint: &Pubkey) -> ProgramResult {
+    if *token_account.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let account = spl_token::state::Account::unpack(&token_account.data.borrow())?;
+    if account.mint != *mint {
+        return Err(TokenError::PublicKeyMismatch.into());
+    }
+    Ok(())
+}
+/// Checks that a party allowed by `cancel_policy` signed the cancel. `recipient` must already be
+/// checked with `assert_stream_payee`, so cancel rights follow the receivable token.
+pub fn assert_cancel_allowed(
+    cancel_policy: u8,
+    sender: &AccountInfo,
+    recipient: &AccountInfo,
+) -> ProgramResult {
+    let sender_signed = sender.is_signer;
+    let recipient_signed = recipient.is_signer;
+    let allowed = match cancel_policy {
+        CANCEL_BY_SENDER => sender_signed,
+        CANCEL_BY_RECIPIENT => recipient_signed,
+        CANCEL_BY_BOTH => sender_signed || recipient_signed,
+        _ => return Err(TokenError::CancelNotAllowed.into()),
+    };
+    if !allowed {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+/// Checks that a party allowed by `pause_policy` signed the pause or resume
+pub fn assert_pause_allowed(
+    pause_policy: u8,
+    sender: &AccountInfo,
+    recipient: &AccountInfo,
+) -> ProgramResult {
+    let allowed = match pause_policy {
+        PAUSE_BY_BOTH => sender.is_signer || recipient.is_signer,
+        PAUSE_BY_SENDER => sender.is_signer,
+        _ => return Err(TokenError::PauseNotAllowed.into()),
+    };
+    if !allowed {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+/// Moves all lamports of a program account to `destination` and wipes its data
+pub fn close_program_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let dest_starting_lamports = destination.lamports();
+    **destination.lamports.borrow_mut() = dest_starting_lamports
+        .checked_add(account.lamports())
+        .ok_or(TokenError::Overflow)?;
+    **account.lamports.borrow_mut() = 0;
+    account.data.borrow_mut().fill(0);
+    Ok(())
+}
 pub fn check_data_len(data: &[u8], min_len: usize) -> Result<(), ProgramError> {
     if data.len() < min_len {
         Err(ProgramError::AccountDataTooSmall)
@@ -154,4 +537,84 @@ pub fn get_token_balance(token_account: &AccountInfo) -> Result<u64, ProgramErro
     check_data_len(&data, spl_token::state::Account::get_packed_len())?;
     let amount = array_ref![data, 64, 8];
     Ok(u64::from_le_bytes(*amount))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breakpoint(time: u64, amount: u64) -> Breakpoint {
+        Breakpoint { time, amount }
+    }
+    fn beneficiary(share_bps: u16) -> Beneficiary {
+        Beneficiary { address: Pubkey::new_unique(), share_bps, withdrawn: 0 }
+    }
+
+    #[test]
+    fn vested_amount_is_linear_between_the_ends() {
+        assert_eq!(calculate_vested_amount(100, 200, 0, 1000, 50).unwrap(), 0);
+        assert_eq!(calculate_vested_amount(100, 200, 0, 1000, 100).unwrap(), 0);
+        assert_eq!(calculate_vested_amount(100, 200, 0, 1000, 150).unwrap(), 500);
+        assert_eq!(calculate_vested_amount(100, 200, 0, 1000, 200).unwrap(), 1000);
+        assert_eq!(calculate_vested_amount(100, 200, 0, 1000, 300).unwrap(), 1000);
+        assert_eq!(calculate_vested_amount(100, 200, 400, 1000, 150).unwrap(), 700);
+    }
+
+    #[test]
+    fn vested_amount_rounds_down() {
+        // 10 over 3 seconds vests 3.33 after one second
+        assert_eq!(calculate_vested_amount(0, 3, 0, 10, 1).unwrap(), 3);
+        assert_eq!(calculate_vested_amount(0, 3, 0, 10, 2).unwrap(), 6);
+        assert_eq!(calculate_vested_amount(0, 3, 0, u64::MAX, 2).unwrap(), u64::MAX / 3 * 2);
+    }
+
+    #[test]
+    fn vested_amount_rejects_a_decreasing_segment() {
+        assert!(calculate_vested_amount(0, 10, 100, 50, 5).is_err());
+    }
+
+    #[test]
+    fn breakpoints_must_be_non_decreasing_and_in_range() {
+        assert!(assert_valid_breakpoints(&[], 100, 200, 1000).is_ok());
+        assert!(assert_valid_breakpoints(&[breakpoint(120, 100), breakpoint(150, 100), breakpoint(200, 1000)], 100, 200, 1000).is_ok());
+        assert!(assert_valid_breakpoints(&[breakpoint(150, 500), breakpoint(140, 600)], 100, 200, 1000).is_err());
+        assert!(assert_valid_breakpoints(&[breakpoint(150, 500), breakpoint(160, 400)], 100, 200, 1000).is_err());
+        assert!(assert_valid_breakpoints(&[breakpoint(90, 0)], 100, 200, 1000).is_err());
+        assert!(assert_valid_breakpoints(&[breakpoint(210, 1000)], 100, 200, 1000).is_err());
+        assert!(assert_valid_breakpoints(&[breakpoint(150, 1001)], 100, 200, 1000).is_err());
+        let too_many: Vec<Breakpoint> = (0..=MAX_BREAKPOINTS as u64).map(|i| breakpoint(100 + i, i)).collect();
+        assert!(assert_valid_breakpoints(&too_many, 100, 200, 1000).is_err());
+    }
+
+    #[test]
+    fn beneficiaries_must_share_the_whole_stream() {
+        assert!(assert_valid_beneficiaries(&[]).is_ok());
+        assert!(assert_valid_beneficiaries(&[beneficiary(2500), beneficiary(7500)]).is_ok());
+        assert!(assert_valid_beneficiaries(&[beneficiary(2500), beneficiary(7000)]).is_err());
+        assert!(assert_valid_beneficiaries(&[beneficiary(0), beneficiary(10000)]).is_err());
+        let twice = beneficiary(5000);
+        assert!(assert_valid_beneficiaries(&[twice, twice]).is_err());
+        let mut paid = beneficiary(5000);
+        paid.withdrawn = 1;
+        assert!(assert_valid_beneficiaries(&[paid, beneficiary(5000)]).is_err());
+        let too_many: Vec<Beneficiary> = (0..=MAX_BENEFICIARIES).map(|_| beneficiary(1)).collect();
+        assert!(assert_valid_beneficiaries(&too_many).is_err());
+    }
+
+    #[test]
+    fn pause_terms_and_metadata_are_checked() {
+        assert!(assert_valid_pause_terms(PAUSE_NONE, false, true).is_ok());
+        assert!(assert_valid_pause_terms(PAUSE_NONE + 1, false, true).is_err());
+        assert!(assert_valid_pause_terms(PAUSE_BY_SENDER, true, true).is_ok());
+        assert!(assert_valid_pause_terms(PAUSE_BY_SENDER, true, false).is_err());
+
+        let mut metadata = StreamMetadata::default();
+        assert!(assert_valid_metadata(&metadata).is_ok());
+        metadata.name[..7].copy_from_slice(b"payroll");
+        assert!(assert_valid_metadata(&metadata).is_ok());
+        metadata.memo[0] = 0xff;
+        assert!(assert_valid_metadata(&metadata).is_err());
+        metadata.memo[0] = 0;
+        metadata.uri[1] = b'x';
+        assert!(assert_valid_metadata(&metadata).is_err());
+    }
+}